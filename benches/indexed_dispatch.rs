@@ -0,0 +1,68 @@
+//! Compares dispatching over 512 exact keys using indexed states and a linear scan over simple state connections. Run
+//! with `cargo bench --bench indexed_dispatch` to see the timings.
+
+use std::{collections::VecDeque, time::Instant};
+
+use automata_like_programming::{
+        automaton::Automaton,
+        automaton_state::new_shared_concrete_state,
+        simple_impl::
+        {
+            indexed_state::{HashIndexedState, OrdIndexedState},
+            simple_state::{KeyProvidingData, SimpleInterStateConnection, SimpleStateImplementation}
+        }
+};
+
+const OPCODES: u16 = 512;
+
+struct Opcodes {
+    keys: VecDeque<u16>,
+    sum: u64,
+}
+
+impl KeyProvidingData<u16> for Opcodes {
+    fn next_key(&mut self) -> Option<u16> {
+        self.keys.pop_front()
+    }
+}
+
+fn add_opcode(d: &mut Opcodes, k: &u16) -> Result<(), String> {
+    d.sum += u64::from(*k);
+    Result::Ok(())
+}
+
+fn main() {
+    let keys: VecDeque<u16> = (0..200_000u32).map(|i| (i * 7919 % u32::from(OPCODES)) as u16).collect();
+
+    let mut linear: Automaton<u8, Opcodes, String> = Automaton::new(|| {
+        let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+        for opcode in 0..OPCODES {
+            state.borrow_mut().register_connection(SimpleInterStateConnection::new(move |k| *k == opcode, add_opcode, &state));
+        }
+        state
+    });
+    let mut hashed: Automaton<u8, Opcodes, String> = Automaton::new(|| {
+        let state = new_shared_concrete_state(HashIndexedState::new(1));
+        for opcode in 0..OPCODES {
+            state.borrow_mut().register_exact_connection(opcode, add_opcode, &state);
+        }
+        state
+    });
+    let mut ordered: Automaton<u8, Opcodes, String> = Automaton::new(|| {
+        let state = new_shared_concrete_state(OrdIndexedState::new(1));
+        for opcode in 0..OPCODES {
+            state.borrow_mut().register_exact_connection(opcode, add_opcode, &state);
+        }
+        state
+    });
+
+    let mut sums = Vec::new();
+    for (name, automaton) in [("linear scan", &mut linear), ("HashMap", &mut hashed), ("BTreeMap", &mut ordered)] {
+        let mut data = Opcodes { keys: keys.clone(), sum: 0 };
+        let start = Instant::now();
+        assert!(automaton.run(&mut data).is_empty_iter());
+        println!("{}: {:?}", name, start.elapsed());
+        sums.push(data.sum);
+    }
+    assert!(sums.iter().all(|sum| *sum == sums[0]));
+}
@@ -6,6 +6,16 @@ use crate::automaton_state::SharedAutomatonState;
 pub enum NextState<'a, Id, D, E> {
     /// Automaton should take provided state for the next iteration.
     Continue(SharedAutomatonState<'a, Id, D, E>),
+    /// Automaton should take provided state for the next iteration without consuming any input (epsilon transition). The
+    /// transition was taken unconditionally, so taking it again will lead to the same state. Automaton stops if consecutive
+    /// unconditional epsilon transitions lead back to an already visited state.
+    Epsilon(SharedAutomatonState<'a, Id, D, E>),
+    /// Automaton should take provided state for the next iteration without consuming any input. The transition was taken
+    /// because a condition (depending on the data) was met, so the cycle detection starts anew - loops of guarded epsilon
+    /// transitions are expected to be ended by their guards.
+    GuardedEpsilon(SharedAutomatonState<'a, Id, D, E>),
+    /// State got stuck in a cycle of epsilon transitions that it manages by itself (e.g. in a nested automaton).
+    EpsilonCycle,
     /// The input data has ended so there is no way for matching next state.
     ProcessEnded,
     /// There are no possible target states for received input data.
@@ -13,6 +23,7 @@ pub enum NextState<'a, Id, D, E> {
 }
 
 /// Iterator for providing next key.
+#[deprecated(note = "use `key_provider::KeyProvidingData` (types implementing `KeyIter` can be wrapped in `key_provider::KeyIterAdapter`)")]
 pub trait KeyIter<K> {
     fn next(&mut self) -> Option<K>;
 }
@@ -20,66 +31,225 @@ pub trait KeyIter<K> {
 /// Finite-state automaton that crawls around a specified graph until no more state changes can be done.
 pub struct Automaton<'a, Id, D, E> {
     start_state: SharedAutomatonState<'a, Id, D, E>,
+    paused_state: Option<SharedAutomatonState<'a, Id, D, E>>,
+    /// States visited through unconditional epsilon transitions since the last transition consuming input. Kept between
+    /// calls of [`Automaton::run_steps`].
+    epsilon_chain: Vec<SharedAutomatonState<'a, Id, D, E>>,
+    /// Number of epsilon transitions (of any kind) taken since the last transition consuming input.
+    epsilon_steps: usize,
+    epsilon_limit: usize,
+    /// Most significant state change taken since the current run has started.
+    progress: RunProgress,
     _data_phantom: PhantomData<D>,
     _error_phantom: PhantomData<E>,
 }
 
+/// Kinds of state changes taken by a run, ordered by significance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum RunProgress {
+    /// No state change or only unconditional epsilon transitions (running again from the same state does the same).
+    Unchanged,
+    /// Guarded epsilon transitions, which can lead elsewhere when the data changes.
+    GuardedEpsilon,
+    /// At least one transition consuming input.
+    ConsumedInput,
+}
+
+/// Default maximal number of consecutive epsilon transitions, see [`Automaton::with_epsilon_limit`].
+pub const DEFAULT_EPSILON_LIMIT: usize = 100_000;
+
 /// Provides information on why automaton has stopped executing.
-pub enum AutomatonResult<Id, E> {
+///
+/// Results of runs that don't track positions of keys (e.g. [`Automaton::run`]) contain `()` as the position. Runs
+/// started with `Automaton::run_positioned` contain position of the key on which the automaton stopped.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AutomatonResult<Id, E, P = ()> {
     // Ok, // Not needed - should end because no more keys, no state could be found or state forces the end of process (no default ending).
     /// Automaton execution ended because no more keys could be extracted. Contains identifier of current state in automaton execution - no more
     /// keys could be extracted after reaching this state.
     EmptyIter(
         Id
     ),
-    /// No connection could be matched for a key. Contains identifier of current state in automaton execution - no connections could be found on this state for given key.
+    /// No connection could be matched for a key. Contains identifier of current state in automaton execution - no connections could be found on this state for given key
+    /// - and position of that key.
     CouldNotFindNextState(
-        Id
+        Id,
+        P
     ),
-    /// An error occured while executing function assigned to connection. Contains error generated while changing state.
+    /// An error occured while executing function assigned to connection. Contains error generated while changing state and
+    /// position of the key that was processed when it occured.
     Error(
-        E
+        E,
+        P
+    ),
+    /// Consecutive unconditional epsilon transitions led back to a state that was already visited since the last transition
+    /// consuming input, more epsilon transitions than the limit of the automaton were taken in a row (or a state reported
+    /// such cycle). Contains identifier of the state from which the last epsilon transition was taken.
+    EpsilonCycle(
+        Id
     )
 }
 
-impl <Id, E> AutomatonResult<Id, E> {
+/// Progress of a run that was paused before the automaton stopped executing. Contains identifier of the state from which
+/// the run will continue, so it can be stored and used for recreating the run later.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutomatonSnapshot<Id> {
+    pub current_state: Id,
+}
+
+impl <Id, E, P> AutomatonResult<Id, E, P> {
     pub fn is_empty_iter(&self) -> bool {
         return matches!(self, AutomatonResult::EmptyIter(_))
     }
 
     pub fn is_could_not_find_next_state(&self) -> bool {
-        return matches!(self, AutomatonResult::CouldNotFindNextState(_))
+        return matches!(self, AutomatonResult::CouldNotFindNextState(_, _))
     }
 
     pub fn is_error(&self) -> bool {
-        return matches!(self, AutomatonResult::Error(_))
+        return matches!(self, AutomatonResult::Error(_, _))
+    }
+
+    pub fn is_epsilon_cycle(&self) -> bool {
+        return matches!(self, AutomatonResult::EpsilonCycle(_))
+    }
+
+    /// Returns position of the key on which the automaton stopped. Returns `None` if the automaton stopped for any other
+    /// reason than an unmatched key or an error.
+    pub fn position(&self) -> Option<&P> {
+        match self {
+            AutomatonResult::CouldNotFindNextState(_, position) | AutomatonResult::Error(_, position) => Option::Some(position),
+            _ => Option::None,
+        }
+    }
+}
+
+impl <Id, E> AutomatonResult<Id, E> {
+    /// Replaces missing position of the result with given position.
+    pub fn with_position<P>(self, position: P) -> AutomatonResult<Id, E, P> {
+        match self {
+            AutomatonResult::EmptyIter(id) => AutomatonResult::EmptyIter(id),
+            AutomatonResult::CouldNotFindNextState(id, ()) => AutomatonResult::CouldNotFindNextState(id, position),
+            AutomatonResult::Error(err, ()) => AutomatonResult::Error(err, position),
+            AutomatonResult::EpsilonCycle(id) => AutomatonResult::EpsilonCycle(id),
+        }
     }
 }
 
 impl <'a, Id, D, E> Automaton<'a, Id, D, E> {
     /// Creates new automaton with graph initiated by specified function.
     pub fn new<FInit: Fn() -> SharedAutomatonState<'a, Id, D, E>>(f_state_graph_init: FInit) -> Self {
-        Self {start_state: f_state_graph_init(), _data_phantom: PhantomData{}, _error_phantom: PhantomData{}}
+        Self {start_state: f_state_graph_init(), paused_state: Option::None, epsilon_chain: Vec::new(), epsilon_steps: 0, epsilon_limit: DEFAULT_EPSILON_LIMIT, progress: RunProgress::Unchanged, _data_phantom: PhantomData{}, _error_phantom: PhantomData{}}
+    }
+
+    /// Sets maximal number of consecutive epsilon transitions (without consuming input). Automaton stops with
+    /// `AutomatonResult::EpsilonCycle` when it's exceeded, which ends loops of guarded epsilon transitions whose guards
+    /// never fail. Defaults to [`DEFAULT_EPSILON_LIMIT`].
+    pub fn with_epsilon_limit(mut self, limit: usize) -> Self {
+        self.epsilon_limit = limit;
+        self
     }
 
     /// Starts automaton with given data.
     pub fn run(&mut self, data: &mut D) -> AutomatonResult<Id, E> {
+        self.paused_state = Option::None;
+        self.epsilon_chain.clear();
+        self.epsilon_steps = 0;
+        self.progress = RunProgress::Unchanged;
         let mut current_state = Rc::clone(&self.start_state);
+        if let Err(err) = current_state.borrow().execute_entry(data) {
+            return AutomatonResult::Error(err, ());
+        }
         loop {
-            let connection_execute_result = current_state.borrow().execute_next_connection(data);
-            match connection_execute_result {
-                Err(err) => {
-                    return AutomatonResult::Error(err);
-                },
-                Ok(next_state_result) => {
-                    match next_state_result {
-                        NextState::Continue(next_state) => current_state = next_state,
-                        NextState::NotFound => return AutomatonResult::CouldNotFindNextState(current_state.borrow().get_id_owned()),
-                        NextState::ProcessEnded => return AutomatonResult::EmptyIter(current_state.borrow().get_id_owned()),
-                    };
-                },
-            };
+            if let Option::Some(result) = self.execute_step(&mut current_state, data) {
+                return result;
+            }
+        };
+    }
+
+    /// Executes at most `max_steps` state changes. Continues from the state on which previous call has paused (or from the
+    /// start state if there is no paused run). Returns `None` if the automaton was paused before it stopped executing - use
+    /// [`Automaton::snapshot`] for retrieving the state on which it was paused.
+    pub fn run_steps(&mut self, data: &mut D, max_steps: usize) -> Option<AutomatonResult<Id, E>> {
+        let mut current_state = match self.paused_state.take() {
+            Option::Some(state) => state,
+            Option::None => {
+                self.epsilon_chain.clear();
+                self.epsilon_steps = 0;
+                self.progress = RunProgress::Unchanged;
+                let start_state = Rc::clone(&self.start_state);
+                if let Err(err) = start_state.borrow().execute_entry(data) {
+                    return Option::Some(AutomatonResult::Error(err, ()));
+                }
+                start_state
+            },
         };
+        for _ in 0..max_steps {
+            if let Option::Some(result) = self.execute_step(&mut current_state, data) {
+                return Option::Some(result);
+            }
+        }
+        self.paused_state = Option::Some(current_state);
+        Option::None
+    }
+
+    /// Returns snapshot of the paused run (started with [`Automaton::run_steps`]). Returns `None` if there is no paused run.
+    pub fn snapshot(&self) -> Option<AutomatonSnapshot<Id>> {
+        self.paused_state.as_ref().map(|state| AutomatonSnapshot { current_state: state.borrow().get_id_owned() })
+    }
+
+    /// Executes single state change. Returns result if automaton should stop executing.
+    fn execute_step(&mut self, current_state: &mut SharedAutomatonState<'a, Id, D, E>, data: &mut D) -> Option<AutomatonResult<Id, E>> {
+        let connection_execute_result = current_state.borrow().execute_next_connection(data);
+        match connection_execute_result {
+            Err(err) => Option::Some(AutomatonResult::Error(err, ())),
+            Ok(next_state_result) => {
+                let next_state = match next_state_result {
+                    NextState::Continue(next_state) => {
+                        self.epsilon_chain.clear();
+                        self.epsilon_steps = 0;
+                        self.progress = RunProgress::ConsumedInput;
+                        next_state
+                    },
+                    NextState::Epsilon(next_state) => {
+                        self.epsilon_chain.push(Rc::clone(current_state));
+                        if self.epsilon_chain.iter().any(|state| Rc::as_ptr(state) as *const () == Rc::as_ptr(&next_state) as *const ()) {
+                            return Option::Some(AutomatonResult::EpsilonCycle(current_state.borrow().get_id_owned()));
+                        }
+                        if self.epsilon_limit_exceeded() {
+                            return Option::Some(AutomatonResult::EpsilonCycle(current_state.borrow().get_id_owned()));
+                        }
+                        next_state
+                    },
+                    NextState::GuardedEpsilon(next_state) => {
+                        self.epsilon_chain.clear();
+                        self.progress = self.progress.max(RunProgress::GuardedEpsilon);
+                        if self.epsilon_limit_exceeded() {
+                            return Option::Some(AutomatonResult::EpsilonCycle(current_state.borrow().get_id_owned()));
+                        }
+                        next_state
+                    },
+                    NextState::EpsilonCycle => return Option::Some(AutomatonResult::EpsilonCycle(current_state.borrow().get_id_owned())),
+                    NextState::NotFound => return Option::Some(AutomatonResult::CouldNotFindNextState(current_state.borrow().get_id_owned(), ())),
+                    NextState::ProcessEnded => return Option::Some(AutomatonResult::EmptyIter(current_state.borrow().get_id_owned())),
+                };
+                *current_state = next_state;
+                let entry_result = current_state.borrow().execute_entry(data);
+                entry_result.err().map(|err| AutomatonResult::Error(err, ()))
+            },
+        }
+    }
+
+    /// Returns most significant state change taken by the last (or current) run.
+    pub(crate) fn progress(&self) -> RunProgress {
+        self.progress
+    }
+
+    /// Counts taken epsilon transition and checks whether there were too many of them in a row.
+    fn epsilon_limit_exceeded(&mut self) -> bool {
+        self.epsilon_steps += 1;
+        self.epsilon_steps > self.epsilon_limit
     }
 }
 
@@ -89,7 +259,7 @@ pub mod test {
 
     use crate::{automaton::AutomatonResult, automaton_state::{new_shared_automaton_state, AutomatonState, SharedAutomatonState}};
 
-    use super::{Automaton, NextState};
+    use super::{Automaton, AutomatonSnapshot, NextState};
 
     pub struct TestNodeHello<'a> {
         next_state: Option<SharedAutomatonState<'a, u8, String, String>>
@@ -160,21 +330,48 @@ pub mod test {
     #[test]
     fn automaton_result_is_empty_iter() -> () {
         assert!(AutomatonResult::<u8, String>::EmptyIter(1).is_empty_iter());
-        assert!(!AutomatonResult::<u8, String>::CouldNotFindNextState(1).is_empty_iter());
-        assert!(!AutomatonResult::<u8, String>::Error(String::from("Test error")).is_empty_iter());
+        assert!(!AutomatonResult::<u8, String>::CouldNotFindNextState(1, ()).is_empty_iter());
+        assert!(!AutomatonResult::<u8, String>::Error(String::from("Test error"), ()).is_empty_iter());
     }
 
     #[test]
     fn automaton_result_is_could_not_find_next_state() -> () {
         assert!(!AutomatonResult::<u8, String>::EmptyIter(1).is_could_not_find_next_state());
-        assert!(AutomatonResult::<u8, String>::CouldNotFindNextState(1).is_could_not_find_next_state());
-        assert!(!AutomatonResult::<u8, String>::Error(String::from("Test error")).is_could_not_find_next_state());
+        assert!(AutomatonResult::<u8, String>::CouldNotFindNextState(1, ()).is_could_not_find_next_state());
+        assert!(!AutomatonResult::<u8, String>::Error(String::from("Test error"), ()).is_could_not_find_next_state());
     }
 
     #[test]
     fn automaton_result_is_error() -> () {
         assert!(!AutomatonResult::<u8, String>::EmptyIter(1).is_error());
-        assert!(!AutomatonResult::<u8, String>::CouldNotFindNextState(1).is_error());
-        assert!(AutomatonResult::<u8, String>::Error(String::from("Test error")).is_error());
+        assert!(!AutomatonResult::<u8, String>::CouldNotFindNextState(1, ()).is_error());
+        assert!(AutomatonResult::<u8, String>::Error(String::from("Test error"), ()).is_error());
+        assert!(!AutomatonResult::<u8, String>::EpsilonCycle(1).is_error());
+    }
+
+    #[test]
+    fn automaton_result_is_epsilon_cycle() {
+        assert!(!AutomatonResult::<u8, String>::EmptyIter(1).is_epsilon_cycle());
+        assert!(!AutomatonResult::<u8, String>::CouldNotFindNextState(1, ()).is_epsilon_cycle());
+        assert!(!AutomatonResult::<u8, String>::Error(String::from("Test error"), ()).is_epsilon_cycle());
+        assert!(AutomatonResult::<u8, String>::EpsilonCycle(1).is_epsilon_cycle());
+    }
+
+    #[test]
+    fn automaton_run_steps_pauses_and_resumes() {
+        let mut data = String::with_capacity(11);
+        let mut automaton = Automaton::new(|| {
+            let world_state: SharedAutomatonState<u8, String, _> = new_shared_automaton_state(TestNodeWorld::new());
+            let hello_state: SharedAutomatonState<u8, String, _> = new_shared_automaton_state(TestNodeHello::new(Option::Some(Rc::clone(&world_state))));
+            hello_state
+        });
+        assert!(automaton.snapshot().is_none());
+        assert!(automaton.run_steps(&mut data, 1).is_none());
+        assert_eq!(data, "Hello");
+        assert_eq!(automaton.snapshot(), Option::Some(AutomatonSnapshot { current_state: 2 }));
+        let run_res = automaton.run_steps(&mut data, 1);
+        assert!(matches!(run_res, Option::Some(AutomatonResult::EmptyIter(2))));
+        assert_eq!(data, "Hello world");
+        assert!(automaton.snapshot().is_none());
     }
 }
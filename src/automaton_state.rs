@@ -13,6 +13,12 @@ pub trait AutomatonState<'a, Id, D, E> {
     /// Represents change of current state in graph. Provides state to be executed by automaton. Implementations should use this method for executing operations connected with
     /// state change.
     fn execute_next_connection(&self, data: &mut D) -> Result<NextState<'a, Id, D, E>, E>;
+
+    /// Executed by automaton whenever this state becomes active - when the run starts on this state or after the connection
+    /// leading to this state was executed. Does nothing by default.
+    fn execute_entry(&self, _data: &mut D) -> Result<(), E> {
+        Result::Ok(())
+    }
 }
 
 pub type SharedAutomatonState<'a, Id, D, E> = Rc<RefCell<dyn AutomatonState<'a, Id, D, E> + 'a>>;
@@ -0,0 +1,222 @@
+use std::{collections::VecDeque, iter::Peekable, str::{Bytes, CharIndices, Chars}, vec};
+
+/// Represents data, that can provide a key which will be used while searching for next state. Usually will use iterator
+/// based on a sequence.
+pub trait KeyProvidingData<K> {
+    fn next_key(&mut self) -> Option<K>;
+
+    /// Receives key for which no connection was found, right before the automaton stops. Data that can take keys back
+    /// should return the key (so the rest of the input can be passed to another automaton) and return `true`. Drops the
+    /// key and returns `false` by default.
+    fn restore_unmatched_key(&mut self, _key: K) -> bool {
+        false
+    }
+}
+
+/// Extension of [`KeyProvidingData`] for data that can look at upcoming keys without consuming them (lookahead).
+pub trait PeekingKeyProvidingData<K>: KeyProvidingData<K> {
+    /// Returns key that would be returned by `next_key` after skipping `offset` keys (`0` means the next key) without
+    /// consuming any keys. Returns `None` if the input ends before that key.
+    fn peek_key(&mut self, offset: usize) -> Option<K>;
+}
+
+/// Extension of [`KeyProvidingData`] for data that can take back keys which were already consumed. Implementations
+/// should also override [`KeyProvidingData::restore_unmatched_key`] (usually with a call to `push_back_key`), so keys that
+/// stopped the automaton aren't lost.
+pub trait PushbackKeyProvidingData<K>: KeyProvidingData<K> {
+    /// Returns key to the data, so it will be returned by the next call of `next_key`. Keys pushed back one after another
+    /// are returned in the reversed order.
+    fn push_back_key(&mut self, key: K);
+}
+
+/// Keys are taken from the referenced source, so a source can be borrowed (also as a trait object) instead of moved.
+impl <K, P: KeyProvidingData<K> + ?Sized> KeyProvidingData<K> for &mut P {
+    fn next_key(&mut self) -> Option<K> {
+        (**self).next_key()
+    }
+
+    fn restore_unmatched_key(&mut self, key: K) -> bool {
+        (**self).restore_unmatched_key(key)
+    }
+}
+
+impl <K, P: PeekingKeyProvidingData<K> + ?Sized> PeekingKeyProvidingData<K> for &mut P {
+    fn peek_key(&mut self, offset: usize) -> Option<K> {
+        (**self).peek_key(offset)
+    }
+}
+
+impl <K, P: PushbackKeyProvidingData<K> + ?Sized> PushbackKeyProvidingData<K> for &mut P {
+    fn push_back_key(&mut self, key: K) {
+        (**self).push_back_key(key);
+    }
+}
+
+/// Keys are taken from the boxed source, so the same automaton can run on sources of different types (boxed as trait
+/// objects).
+impl <K, P: KeyProvidingData<K> + ?Sized> KeyProvidingData<K> for Box<P> {
+    fn next_key(&mut self) -> Option<K> {
+        (**self).next_key()
+    }
+
+    fn restore_unmatched_key(&mut self, key: K) -> bool {
+        (**self).restore_unmatched_key(key)
+    }
+}
+
+impl <K, P: PeekingKeyProvidingData<K> + ?Sized> PeekingKeyProvidingData<K> for Box<P> {
+    fn peek_key(&mut self, offset: usize) -> Option<K> {
+        (**self).peek_key(offset)
+    }
+}
+
+impl <K, P: PushbackKeyProvidingData<K> + ?Sized> PushbackKeyProvidingData<K> for Box<P> {
+    fn push_back_key(&mut self, key: K) {
+        (**self).push_back_key(key);
+    }
+}
+
+/// Adapter providing keys from a type implementing the deprecated [`KeyIter`](crate::automaton::KeyIter) trait.
+pub struct KeyIterAdapter<T>(pub T);
+
+#[allow(deprecated)]
+impl <K, T: crate::automaton::KeyIter<K>> KeyProvidingData<K> for KeyIterAdapter<T> {
+    fn next_key(&mut self) -> Option<K> {
+        self.0.next()
+    }
+}
+
+/// Queue of keys is consumed from the front.
+impl <K> KeyProvidingData<K> for VecDeque<K> {
+    fn next_key(&mut self) -> Option<K> {
+        self.pop_front()
+    }
+
+    fn restore_unmatched_key(&mut self, key: K) -> bool {
+        self.push_front(key);
+        true
+    }
+}
+
+impl <K: Clone> PeekingKeyProvidingData<K> for VecDeque<K> {
+    fn peek_key(&mut self, offset: usize) -> Option<K> {
+        self.get(offset).cloned()
+    }
+}
+
+impl <K> PushbackKeyProvidingData<K> for VecDeque<K> {
+    fn push_back_key(&mut self, key: K) {
+        self.push_front(key);
+    }
+}
+
+impl <K> KeyProvidingData<K> for vec::IntoIter<K> {
+    fn next_key(&mut self) -> Option<K> {
+        self.next()
+    }
+}
+
+impl <K: Clone> PeekingKeyProvidingData<K> for vec::IntoIter<K> {
+    fn peek_key(&mut self, offset: usize) -> Option<K> {
+        self.as_slice().get(offset).cloned()
+    }
+}
+
+impl KeyProvidingData<char> for Chars<'_> {
+    fn next_key(&mut self) -> Option<char> {
+        self.next()
+    }
+}
+
+impl PeekingKeyProvidingData<char> for Chars<'_> {
+    fn peek_key(&mut self, offset: usize) -> Option<char> {
+        self.clone().nth(offset)
+    }
+}
+
+impl KeyProvidingData<(usize, char)> for CharIndices<'_> {
+    fn next_key(&mut self) -> Option<(usize, char)> {
+        self.next()
+    }
+}
+
+impl PeekingKeyProvidingData<(usize, char)> for CharIndices<'_> {
+    fn peek_key(&mut self, offset: usize) -> Option<(usize, char)> {
+        self.clone().nth(offset)
+    }
+}
+
+impl KeyProvidingData<u8> for Bytes<'_> {
+    fn next_key(&mut self) -> Option<u8> {
+        self.next()
+    }
+}
+
+impl PeekingKeyProvidingData<u8> for Bytes<'_> {
+    fn peek_key(&mut self, offset: usize) -> Option<u8> {
+        self.clone().nth(offset)
+    }
+}
+
+impl <I: Iterator> KeyProvidingData<I::Item> for Peekable<I> {
+    fn next_key(&mut self) -> Option<I::Item> {
+        self.next()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use super::{KeyIterAdapter, KeyProvidingData, PeekingKeyProvidingData, PushbackKeyProvidingData};
+
+    #[test]
+    fn queue_provides_keys_from_front() {
+        let mut keys = VecDeque::from(vec![1, 2]);
+        assert_eq!(keys.peek_key(1), Option::Some(2));
+        assert_eq!(keys.next_key(), Option::Some(1));
+        assert_eq!(keys.peek_key(1), Option::None);
+    }
+
+    #[test]
+    fn std_iterators_provide_keys() {
+        let mut chars = "ąbc".chars();
+        assert_eq!(chars.peek_key(1), Option::Some('b'));
+        assert_eq!(chars.next_key(), Option::Some('ą'));
+        let mut indices = "ąb".char_indices();
+        assert_eq!(indices.peek_key(1), Option::Some((2, 'b')));
+        assert_eq!("ab".bytes().peek_key(1), Option::Some(b'b'));
+        let mut vector = vec![1, 2, 3].into_iter();
+        assert_eq!(vector.next_key(), Option::Some(1));
+        assert_eq!(vector.peek_key(1), Option::Some(3));
+        assert_eq!([4, 5].into_iter().peekable().next_key(), Option::Some(4));
+    }
+
+    #[test]
+    fn borrowed_sources_provide_keys() {
+        fn replace_first<P: PushbackKeyProvidingData<i32>>(mut source: P) -> bool {
+            source.next_key();
+            source.push_back_key(0);
+            source.restore_unmatched_key(-1)
+        }
+        let mut queue = VecDeque::from(vec![1, 2]);
+        assert!(replace_first(&mut queue as &mut dyn PushbackKeyProvidingData<i32>));
+        assert_eq!(queue, vec![-1, 0, 2]);
+    }
+
+    struct Countdown(u8);
+
+    #[allow(deprecated)]
+    impl crate::automaton::KeyIter<u8> for Countdown {
+        fn next(&mut self) -> Option<u8> {
+            self.0 = self.0.checked_sub(1)?;
+            Option::Some(self.0)
+        }
+    }
+
+    #[test]
+    fn key_iter_adapter_provides_keys() {
+        let mut keys = KeyIterAdapter(Countdown(2));
+        assert_eq!((keys.next_key(), keys.next_key(), keys.next_key()), (Option::Some(1), Option::Some(0), Option::None));
+    }
+}
@@ -76,5 +76,12 @@
 pub mod automaton_state;
 /// Core mechanism representing an automaton that travels through defined states.
 pub mod automaton;
+/// Sources of keys used by automaton states for choosing next state, along with implementations for standard types.
+pub mod key_provider;
 /// Simple implementations of automaton state.
-pub mod simple_impl;
\ No newline at end of file
+pub mod simple_impl;
+
+/// Examples from the README, compiled and run as documentation tests.
+#[cfg(doctest)]
+#[doc = include_str!("../README.md")]
+pub struct ReadmeDoctests;
@@ -0,0 +1,130 @@
+use std::{collections::HashMap, hash::Hash, rc::Rc};
+
+use crate::{automaton::Automaton, automaton_state::{convert_to_dyn_reference, new_shared_concrete_state}, simple_impl::{definition::DefinitionError, simple_state::{ConnectionAction, KeyMatcher, KeyProvidingData, SimpleInterStateConnection, SimpleStateImplementation}}};
+
+/// Connection waiting for its states to be created.
+struct PendingConnection<'a, K, Id, D, E> {
+    source: Id,
+    target: Id,
+    matcher: KeyMatcher<'a, K>,
+    exec_function: ConnectionAction<'a, K, D, E>,
+}
+
+/// Creates automaton made of [`SimpleStateImplementation`] states where states and connections refer to each other by
+/// identifiers. States are created only when [`AutomatonBuilder::build`] is called, so connections can point to states
+/// declared after them and the order of declarations doesn't matter (except for the order of connections registered
+/// on the same state, which decides which connection is matched first).
+pub struct AutomatonBuilder<'a, K, Id, D, E> where D: KeyProvidingData<K>, Id: Copy {
+    start_state: Id,
+    states: Vec<Id>,
+    connections: Vec<PendingConnection<'a, K, Id, D, E>>,
+}
+
+impl <'a, K, Id, D, E> AutomatonBuilder<'a, K, Id, D, E> where D: KeyProvidingData<K> + 'a, Id: Copy + Eq + Hash + 'a, K: 'a, E: 'a {
+    /// Creates builder for an automaton that will start on the state with provided identifier. The start state is
+    /// declared by this call (like in [`AutomatonDefinition::new`](crate::simple_impl::definition::AutomatonDefinition::new)),
+    /// so it must not be added again with [`AutomatonBuilder::add_state`].
+    pub fn new(start_state: Id) -> Self {
+        Self { start_state, states: vec![start_state], connections: Vec::new() }
+    }
+
+    /// Declares state with provided identifier.
+    pub fn add_state(&mut self, id: Id) {
+        self.states.push(id);
+    }
+
+    /// Declares connection between states with provided identifiers. Procedure will be executed when this connection is matched.
+    pub fn add_connection<M: Fn(&K) -> bool + 'a, FExec: Fn(&mut D, &K) -> Result<(), E> + 'a>(&mut self, source: Id, matcher: M, exec_function: FExec, target: Id) {
+        self.connections.push(PendingConnection { source, target, matcher: Box::new(matcher), exec_function: Box::new(exec_function) });
+    }
+
+    /// Declares connection between states with provided identifiers. Does nothing when matched.
+    pub fn add_connection_no_action<M: Fn(&K) -> bool + 'a>(&mut self, source: Id, matcher: M, target: Id) {
+        self.add_connection(source, matcher, |_: &mut D, _: &K| Result::Ok(()), target);
+    }
+
+    /// Creates declared states, resolves connections and returns automaton starting on the start state. Fails if an
+    /// identifier was declared more than once or if the start state or any connection refers to an undeclared state.
+    pub fn build(self) -> Result<Automaton<'a, Id, D, E>, DefinitionError<Id>> {
+        let start_state = self.start_state;
+        self.build_starting_at(start_state)
+    }
+
+    /// Builds automaton which will start on the state with provided identifier instead of the start state.
+    pub(crate) fn build_starting_at(self, start_state: Id) -> Result<Automaton<'a, Id, D, E>, DefinitionError<Id>> {
+        let mut states = HashMap::with_capacity(self.states.len());
+        for id in self.states {
+            if states.insert(id, new_shared_concrete_state(SimpleStateImplementation::new(id))).is_some() {
+                return Result::Err(DefinitionError::DuplicateState(id));
+            }
+        }
+        let start = convert_to_dyn_reference(Rc::clone(states.get(&start_state).ok_or(DefinitionError::UnknownStartState(start_state))?));
+        for connection in self.connections {
+            let source = states.get(&connection.source).ok_or(DefinitionError::UnknownConnectionSource(connection.source))?;
+            let target = states.get(&connection.target).ok_or(DefinitionError::UnknownConnectionTarget(connection.target))?;
+            source.borrow_mut().register_connection(SimpleInterStateConnection::new(connection.matcher, connection.exec_function, target));
+        }
+        Result::Ok(Automaton::new(move || Rc::clone(&start)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use crate::{automaton::AutomatonResult, simple_impl::{definition::DefinitionError, key_sources::KeyedData}};
+
+    use super::AutomatonBuilder;
+
+    type TestData = KeyedData<VecDeque<u8>, String>;
+
+    #[test]
+    fn builder_resolves_forward_references() {
+        let mut builder = AutomatonBuilder::new(1);
+        builder.add_connection(1, |k| *k == 1, |d: &mut TestData, _| {
+            d.data.push_str("Hello");
+            Result::Ok(())
+        }, 2);
+        builder.add_connection_no_action(2, |k| *k == 2, 2);
+        builder.add_connection(2, |k| *k == 3, |d: &mut TestData, _| {
+            d.data.push_str(" world");
+            Result::Ok(())
+        }, 3);
+        builder.add_state(3);
+        builder.add_state(2);
+        let mut automaton = builder.build().unwrap();
+        let mut data = KeyedData::new(VecDeque::from(vec![1, 2, 2, 3]), String::new());
+        let result: AutomatonResult<u8, String> = automaton.run(&mut data);
+        assert!(matches!(result, AutomatonResult::EmptyIter(3)));
+        assert_eq!(data.data, "Hello world");
+    }
+
+    #[test]
+    fn builder_rejects_duplicate_states() {
+        let mut builder: AutomatonBuilder<u8, u8, TestData, String> = AutomatonBuilder::new(1);
+        builder.add_state(1);
+        let error = builder.build().err().unwrap();
+        assert_eq!(error, DefinitionError::DuplicateState(1));
+        assert_eq!(error.to_string(), "state 1 is declared more than once");
+    }
+
+    #[test]
+    fn builder_rejects_dangling_references() {
+        let mut builder: AutomatonBuilder<u8, u8, TestData, String> = AutomatonBuilder::new(1);
+        builder.add_connection_no_action(1, |k| *k == 1, 4);
+        let error = builder.build().err().unwrap();
+        assert_eq!(error, DefinitionError::UnknownConnectionTarget(4));
+        assert_eq!(error.to_string(), "connection leads to undeclared state 4");
+
+        let mut builder: AutomatonBuilder<u8, u8, TestData, String> = AutomatonBuilder::new(1);
+        builder.add_connection_no_action(5, |k| *k == 1, 1);
+        assert_eq!(builder.build().err().unwrap(), DefinitionError::UnknownConnectionSource(5));
+    }
+
+    #[test]
+    fn builder_declares_start_state() {
+        let builder: AutomatonBuilder<u8, u8, TestData, String> = AutomatonBuilder::new(1);
+        let mut data = KeyedData::new(VecDeque::from(vec![1]), String::new());
+        assert!(matches!(builder.build().unwrap().run(&mut data), AutomatonResult::CouldNotFindNextState(1, _)));
+    }
+}
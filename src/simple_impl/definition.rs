@@ -0,0 +1,196 @@
+use std::{fmt::{Debug, Display}, hash::Hash};
+
+use crate::{automaton::{Automaton, AutomatonSnapshot}, simple_impl::{builder::AutomatonBuilder, simple_state::{ConnectionAction, KeyMatcher, KeyProvidingData}}};
+
+/// Description of an edge between two states. Matcher and operation of the connection can't be stored directly, so
+/// each connection carries a label which is used for recreating them when the automaton is built.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionDefinition<Id, L> {
+    pub source: Id,
+    pub target: Id,
+    pub label: L,
+}
+
+/// Structure of an automaton graph built from [`SimpleStateImplementation`](crate::simple_impl::simple_state::SimpleStateImplementation)
+/// states. Contains identifiers of all states, labeled connections between them and identifier of the start state. Can be
+/// used for storing automata configurations (e.g. with the `serde` feature enabled) and building equivalent automata from them.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutomatonDefinition<Id, L> {
+    pub start_state: Id,
+    pub states: Vec<Id>,
+    pub connections: Vec<ConnectionDefinition<Id, L>>,
+}
+
+/// Reason why an automaton could not be built from its definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DefinitionError<Id> {
+    /// More than one state was declared with the same identifier.
+    DuplicateState(Id),
+    /// Automaton should start on a state that was not declared.
+    UnknownStartState(Id),
+    /// Connection starts from a state that was not declared.
+    UnknownConnectionSource(Id),
+    /// Connection leads to a state that was not declared.
+    UnknownConnectionTarget(Id),
+}
+
+impl <Id: Debug> Display for DefinitionError<Id> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DefinitionError::DuplicateState(id) => write!(f, "state {:?} is declared more than once", id),
+            DefinitionError::UnknownStartState(id) => write!(f, "start state {:?} is not declared", id),
+            DefinitionError::UnknownConnectionSource(id) => write!(f, "connection starts from undeclared state {:?}", id),
+            DefinitionError::UnknownConnectionTarget(id) => write!(f, "connection leads to undeclared state {:?}", id),
+        }
+    }
+}
+
+impl <Id: Debug> std::error::Error for DefinitionError<Id> {}
+
+impl <Id, L> AutomatonDefinition<Id, L> where Id: Copy + Eq + Hash {
+    /// Creates definition containing only the start state.
+    pub fn new(start_state: Id) -> Self {
+        Self { start_state, states: vec![start_state], connections: Vec::new() }
+    }
+
+    /// Declares new state.
+    pub fn add_state(&mut self, id: Id) {
+        self.states.push(id);
+    }
+
+    /// Declares new connection. Connections are registered in the order of declaration.
+    pub fn add_connection(&mut self, source: Id, label: L, target: Id) {
+        self.connections.push(ConnectionDefinition { source, target, label });
+    }
+
+    /// Builds automaton described by this definition.
+    ///
+    /// * `connection_factory` - Recreates matcher and operation for a connection based on its label.
+    pub fn build<'a, K, D, E, F>(&self, connection_factory: F) -> Result<Automaton<'a, Id, D, E>, DefinitionError<Id>>
+    where F: FnMut(&L) -> (KeyMatcher<'a, K>, ConnectionAction<'a, K, D, E>), Id: 'a, K: 'a, D: KeyProvidingData<K> + 'a, E: 'a {
+        self.build_starting_at(self.start_state, connection_factory)
+    }
+
+    /// Builds automaton described by this definition which will start on the state a previous run was paused on.
+    pub fn build_from_snapshot<'a, K, D, E, F>(&self, snapshot: &AutomatonSnapshot<Id>, connection_factory: F) -> Result<Automaton<'a, Id, D, E>, DefinitionError<Id>>
+    where F: FnMut(&L) -> (KeyMatcher<'a, K>, ConnectionAction<'a, K, D, E>), Id: 'a, K: 'a, D: KeyProvidingData<K> + 'a, E: 'a {
+        self.build_starting_at(snapshot.current_state, connection_factory)
+    }
+
+    fn build_starting_at<'a, K, D, E, F>(&self, start_state: Id, mut connection_factory: F) -> Result<Automaton<'a, Id, D, E>, DefinitionError<Id>>
+    where F: FnMut(&L) -> (KeyMatcher<'a, K>, ConnectionAction<'a, K, D, E>), Id: 'a, K: 'a, D: KeyProvidingData<K> + 'a, E: 'a {
+        let start_position = self.states.iter().position(|id| *id == self.start_state).ok_or(DefinitionError::UnknownStartState(self.start_state))?;
+        let mut builder = AutomatonBuilder::new(self.start_state);
+        for (position, id) in self.states.iter().enumerate() {
+            if position != start_position {
+                builder.add_state(*id);
+            }
+        }
+        for connection in &self.connections {
+            let (matcher, exec_function) = connection_factory(&connection.label);
+            builder.add_connection(connection.source, matcher, exec_function, connection.target);
+        }
+        builder.build_starting_at(start_state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use crate::{automaton::{AutomatonResult, AutomatonSnapshot}, simple_impl::{key_sources::KeyedData, simple_state::{ConnectionAction, KeyMatcher}}};
+
+    use super::{AutomatonDefinition, DefinitionError};
+
+    type TestData = KeyedData<VecDeque<char>, String>;
+
+    fn connection_factory<'a>(label: &char) -> (KeyMatcher<'a, char>, ConnectionAction<'a, char, TestData, String>) {
+        let label = *label;
+        (Box::new(move |k| *k == label), Box::new(|d: &mut TestData, k| {
+            d.data.push(*k);
+            Result::Ok(())
+        }))
+    }
+
+    fn ab_definition() -> AutomatonDefinition<u32, char> {
+        let mut definition = AutomatonDefinition::new(0);
+        definition.add_state(1);
+        definition.add_state(2);
+        definition.add_connection(0, 'a', 1);
+        definition.add_connection(1, 'b', 2);
+        definition.add_connection(2, 'a', 1);
+        definition
+    }
+
+    #[test]
+    fn definition_builds_automaton() {
+        let mut automaton = ab_definition().build(connection_factory).unwrap();
+        let mut data = KeyedData::new("ababb".chars().collect(), String::new());
+        let result = automaton.run(&mut data);
+        assert!(matches!(result, AutomatonResult::CouldNotFindNextState(2, _)));
+        assert_eq!(data.data, "abab");
+    }
+
+    #[test]
+    fn definition_builds_automaton_from_snapshot() {
+        let mut automaton = ab_definition().build(connection_factory).unwrap();
+        let mut data = KeyedData::new("abab".chars().collect(), String::new());
+        assert!(automaton.run_steps(&mut data, 3).is_none());
+        let snapshot = automaton.snapshot().unwrap();
+        assert_eq!(snapshot, AutomatonSnapshot { current_state: 1 });
+        let mut resumed = ab_definition().build_from_snapshot(&snapshot, connection_factory).unwrap();
+        let result = resumed.run(&mut data);
+        assert!(matches!(result, AutomatonResult::EmptyIter(2)));
+        assert_eq!(data.data, "abab");
+    }
+
+    #[test]
+    fn definition_rejects_invalid_graphs() {
+        let mut duplicate = ab_definition();
+        duplicate.add_state(1);
+        assert!(matches!(duplicate.build(connection_factory), Result::Err(DefinitionError::DuplicateState(1))));
+        let mut dangling = ab_definition();
+        dangling.add_connection(2, 'c', 5);
+        assert!(matches!(dangling.build(connection_factory), Result::Err(DefinitionError::UnknownConnectionTarget(5))));
+        let mut unknown_source = ab_definition();
+        unknown_source.add_connection(7, 'c', 0);
+        assert!(matches!(unknown_source.build(connection_factory), Result::Err(DefinitionError::UnknownConnectionSource(7))));
+        let missing = ab_definition();
+        let snapshot = AutomatonSnapshot { current_state: 9 };
+        assert!(matches!(missing.build_from_snapshot(&snapshot, connection_factory), Result::Err(DefinitionError::UnknownStartState(9))));
+    }
+
+    #[cfg(feature = "serde")]
+    mod serde_test {
+        use crate::{automaton::{AutomatonResult, AutomatonSnapshot}, simple_impl::{definition::{test::{ab_definition, connection_factory}, AutomatonDefinition}, key_sources::KeyedData}};
+
+        #[test]
+        fn definition_round_trip_builds_equivalent_automaton() {
+            let json = serde_json::to_string(&ab_definition()).unwrap();
+            let definition: AutomatonDefinition<u32, char> = serde_json::from_str(&json).unwrap();
+            assert_eq!(definition, ab_definition());
+            let mut automaton = definition.build(connection_factory).unwrap();
+            let mut data = KeyedData::new("ababb".chars().collect(), String::new());
+            let result = automaton.run(&mut data);
+            let result_json = serde_json::to_string(&result).unwrap();
+            let result: AutomatonResult<u32, String> = serde_json::from_str(&result_json).unwrap();
+            assert!(matches!(result, AutomatonResult::CouldNotFindNextState(2, _)));
+            assert_eq!(data.data, "abab");
+        }
+
+        #[test]
+        fn snapshot_round_trip_resumes_run() {
+            let mut automaton = ab_definition().build(connection_factory).unwrap();
+            let mut data = KeyedData::new("abab".chars().collect(), String::new());
+            assert!(automaton.run_steps(&mut data, 2).is_none());
+            let json = serde_json::to_string(&automaton.snapshot().unwrap()).unwrap();
+            let snapshot: AutomatonSnapshot<u32> = serde_json::from_str(&json).unwrap();
+            let mut resumed = ab_definition().build_from_snapshot(&snapshot, connection_factory).unwrap();
+            let result = resumed.run(&mut data);
+            assert!(matches!(result, AutomatonResult::EmptyIter(2)));
+            assert_eq!(data.data, "abab");
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::{collections::HashMap, fmt::{Debug, Display}, iter::once};
+
+use crate::{key_provider::KeyProvidingData, simple_impl::{definition::AutomatonDefinition, nfa::{Nfa, NfaMatcher, NfaResult}, range_set::{RangeKey, RangeSet}}};
+
+/// Reason why a nondeterministic automaton could not be converted into a deterministic one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeterminizationError<Id> {
+    /// Connection of the state uses a predicate, so the keys it accepts can't be inspected.
+    UninspectableMatcher(Id),
+}
+
+impl <Id: Debug> Display for DeterminizationError<Id> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeterminizationError::UninspectableMatcher(id) => write!(f, "state {:?} has a connection matching keys with a predicate", id),
+        }
+    }
+}
+
+impl <Id: Debug> std::error::Error for DeterminizationError<Id> {}
+
+/// State of a deterministic automaton, corresponding to a set of states of the nondeterministic automaton it was
+/// created from.
+pub struct DfaState<K, Id> {
+    nfa_states: Vec<Id>,
+    accepting: bool,
+    transitions: Vec<(K, K, usize)>,
+}
+
+impl <K, Id> DfaState<K, Id> {
+    /// Returns identifiers of the nondeterministic automaton states this state stands for, in the order of declaration.
+    pub fn nfa_states(&self) -> &[Id] {
+        &self.nfa_states
+    }
+
+    /// Checks whether any of the nondeterministic automaton states this state stands for is accepting.
+    pub fn is_accepting(&self) -> bool {
+        self.accepting
+    }
+
+    /// Returns transitions as inclusive key ranges along with indices of target states. Ranges are sorted and disjoint.
+    pub fn transitions(&self) -> &[(K, K, usize)] {
+        &self.transitions
+    }
+}
+
+/// Deterministic automaton compiled from an [`Nfa`] with subset construction. States are stored in a table and
+/// identified by their indices (the start state has index `0`), transitions of each state are found with a binary
+/// search. Each state keeps identifiers of the nondeterministic automaton states it was created from, for debugging.
+pub struct Dfa<K, Id> {
+    states: Vec<DfaState<K, Id>>,
+}
+
+impl <K, Id> Dfa<K, Id> {
+    /// Returns all states. Transitions refer to states by their positions in this slice.
+    pub fn states(&self) -> &[DfaState<K, Id>] {
+        &self.states
+    }
+
+    /// Returns index of the start state.
+    pub fn start_state(&self) -> usize {
+        0
+    }
+
+    /// Returns identifiers of the nondeterministic automaton states the state with provided index stands for.
+    pub fn nfa_states(&self, state: usize) -> &[Id] {
+        &self.states[state].nfa_states
+    }
+}
+
+impl <K: Ord, Id: Copy> Dfa<K, Id> {
+    /// Returns index of the state reached from provided state with the key. Returns `None` if no transition matches.
+    pub fn next_state(&self, state: usize, key: &K) -> Option<usize> {
+        let transitions = &self.states[state].transitions;
+        let following = transitions.partition_point(|(start, _, _)| start <= key);
+        (following > 0 && transitions[following - 1].1 >= *key).then(|| transitions[following - 1].2)
+    }
+
+    /// Consumes keys until they end or until no transition matches. The result is the same as the result of running the
+    /// nondeterministic automaton on the same keys - `active_states` contains states the current state stands for.
+    pub fn run<D: KeyProvidingData<K>>(&self, data: &mut D) -> NfaResult<Id> {
+        let mut state = self.start_state();
+        let mut consumed_keys = 0;
+        while let Option::Some(key) = data.next_key() {
+            consumed_keys += 1;
+            match self.next_state(state, &key) {
+                Option::Some(next_state) => state = next_state,
+                Option::None => return NfaResult { accepted: false, active_states: Vec::new(), consumed_keys },
+            }
+        }
+        let state = &self.states[state];
+        NfaResult { accepted: state.accepting, active_states: state.nfa_states.clone(), consumed_keys }
+    }
+}
+
+impl <K: RangeKey, Id> Dfa<K, Id> {
+    /// Creates definition of an equivalent graph made of [`SimpleStateImplementation`](crate::simple_impl::simple_state::SimpleStateImplementation)
+    /// states, identified by indices of states of this automaton. Each connection is labeled with the keys it accepts, and
+    /// connections of a state accept disjoint keys, so their order doesn't matter. Whether the state on which the built
+    /// automaton stopped is accepting can be checked with [`DfaState::is_accepting`].
+    pub fn definition(&self) -> AutomatonDefinition<usize, RangeSet<K>> {
+        let mut definition = AutomatonDefinition::new(self.start_state());
+        for state in 1..self.states.len() {
+            definition.add_state(state);
+        }
+        for (source, state) in self.states.iter().enumerate() {
+            let mut connections: Vec<(usize, RangeSet<K>)> = Vec::new();
+            for (start, end, target) in &state.transitions {
+                match connections.iter_mut().find(|(existing, _)| existing == target) {
+                    Option::Some((_, ranges)) => ranges.insert(*start..=*end),
+                    Option::None => connections.push((*target, RangeSet::from_ranges([*start..=*end]))),
+                }
+            }
+            for (target, ranges) in connections {
+                definition.add_connection(source, ranges, target);
+            }
+        }
+        definition
+    }
+}
+
+impl <K: RangeKey, Id: Copy> Nfa<'_, K, Id> {
+    /// Converts this automaton into a deterministic one with subset construction. Only states reachable from the start
+    /// state are created. Fails if any connection matches keys with a predicate.
+    pub fn determinize(&self) -> Result<Dfa<K, Id>, DeterminizationError<Id>> {
+        let mut edges: Vec<Vec<(K, K, &[usize])>> = Vec::with_capacity(self.states().len());
+        for state in self.states() {
+            let mut state_edges = Vec::new();
+            for connection in state.connections() {
+                match connection.matcher() {
+                    NfaMatcher::Key(key) => state_edges.push((*key, *key, connection.targets())),
+                    NfaMatcher::Ranges(ranges) => state_edges.extend(ranges.ranges().iter().map(|(start, end)| (*start, *end, connection.targets()))),
+                    NfaMatcher::Predicate(_) => return Result::Err(DeterminizationError::UninspectableMatcher(*state.id())),
+                }
+            }
+            edges.push(state_edges);
+        }
+
+        let start = self.epsilon_closure([self.start_state()]);
+        let mut indices: HashMap<Vec<usize>, usize> = HashMap::from([(start.clone(), 0)]);
+        let mut sets = vec![start];
+        let mut states = Vec::new();
+        while states.len() < sets.len() {
+            let set = &sets[states.len()];
+            let set_edges: Vec<&(K, K, &[usize])> = set.iter().flat_map(|state| edges[*state].iter()).collect();
+            // Keys between two consecutive boundaries are matched by the same edges.
+            let mut boundaries: Vec<K> = set_edges.iter().flat_map(|(start, end, _)| once(*start).chain(end.successor())).collect();
+            boundaries.sort();
+            boundaries.dedup();
+            let mut transitions: Vec<(K, K, usize)> = Vec::new();
+            let mut new_sets = Vec::new();
+            for (position, piece_start) in boundaries.iter().enumerate() {
+                let matching: Vec<&&(K, K, &[usize])> = set_edges.iter().filter(|(start, end, _)| start <= piece_start && piece_start <= end).collect();
+                let piece_end = match boundaries.get(position + 1) {
+                    Option::Some(next) => next.predecessor().unwrap_or(*piece_start),
+                    Option::None => match matching.iter().map(|(_, end, _)| *end).max() {
+                        Option::Some(end) => end,
+                        Option::None => continue,
+                    },
+                };
+                let target_set = self.epsilon_closure(matching.iter().flat_map(|(_, _, targets)| targets.iter().copied()));
+                if target_set.is_empty() {
+                    continue;
+                }
+                let next_index = sets.len() + new_sets.len();
+                let target = *indices.entry(target_set).or_insert_with_key(|target_set| {
+                    new_sets.push(target_set.clone());
+                    next_index
+                });
+                match transitions.last_mut() {
+                    Option::Some(last) if last.2 == target && last.1.successor() == Option::Some(*piece_start) => last.1 = piece_end,
+                    _ => transitions.push((*piece_start, piece_end, target)),
+                }
+            }
+            states.push(DfaState {
+                nfa_states: set.iter().map(|state| *self.states()[*state].id()).collect(),
+                accepting: set.iter().any(|state| self.states()[*state].is_accepting()),
+                transitions,
+            });
+            sets.extend(new_sets);
+        }
+        Result::Ok(Dfa { states })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{automaton::AutomatonResult, simple_impl::{key_sources::{chars, PeekableKeys}, nfa::{Nfa, NfaBuilder}, range_set::RangeSet, simple_state::{ConnectionAction, KeyMatcher}}};
+
+    use super::DeterminizationError;
+
+    /// Accepts the "if" keyword and identifiers made of lowercase letters and digits. Keyword and identifier connections
+    /// overlap, so states of the deterministic automaton stand for several states.
+    fn keyword_or_identifier<'a>() -> Nfa<'a, char, &'static str> {
+        let mut builder = NfaBuilder::new("start");
+        builder.add_state("start");
+        builder.add_state("i");
+        builder.add_accepting_state("keyword");
+        builder.add_state("letter");
+        builder.add_accepting_state("identifier");
+        builder.add_connection("start", 'i', ["i"]);
+        builder.add_connection("i", 'f', ["keyword"]);
+        builder.add_epsilon_connection("start", "letter");
+        builder.add_range_connection("letter", RangeSet::from_ranges(['a'..='z']), ["identifier"]);
+        builder.add_range_connection("identifier", RangeSet::from_ranges(['a'..='z', '0'..='9']), ["identifier"]);
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn dfa_accepts_same_inputs_as_nfa() {
+        let nfa = keyword_or_identifier();
+        let dfa = nfa.determinize().unwrap();
+        for text in ["", "i", "if", "ifs", "x1", "1x", "if2", "iff", "a-b", "hi"] {
+            assert_eq!(dfa.run(&mut chars(text)), nfa.run(&mut chars(text)), "input {:?}", text);
+        }
+        assert_eq!(dfa.states().len(), 4);
+        let after_i = dfa.next_state(dfa.start_state(), &'i').unwrap();
+        assert_eq!(dfa.nfa_states(after_i), &["i", "identifier"]);
+        assert_eq!(dfa.states()[after_i].transitions(), &[('0', '9', 1), ('a', 'e', 1), ('f', 'f', 3), ('g', 'z', 1)]);
+        assert_eq!(dfa.nfa_states(dfa.start_state()), &["start", "letter"]);
+    }
+
+    #[test]
+    fn determinize_rejects_predicates() {
+        let mut builder = NfaBuilder::new(0);
+        builder.add_state(0);
+        builder.add_state(1);
+        builder.add_connection(0, 'a', [1]);
+        builder.add_predicate_connection(1, |k: &char| k.is_alphabetic(), [1]);
+        let error = builder.build().unwrap().determinize().err().unwrap();
+        assert_eq!(error, DeterminizationError::UninspectableMatcher(1));
+        assert_eq!(error.to_string(), "state 1 has a connection matching keys with a predicate");
+    }
+
+    fn connection_factory<'a, 't>(ranges: &RangeSet<char>) -> (KeyMatcher<'a, char>, ConnectionAction<'a, char, PeekableKeys<std::str::Chars<'t>>, String>) {
+        let ranges = ranges.clone();
+        (Box::new(move |k| ranges.contains(k)), Box::new(|_, _| Result::Ok(())))
+    }
+
+    #[test]
+    fn dfa_definition_builds_simple_states() {
+        let dfa = keyword_or_identifier().determinize().unwrap();
+        let definition = dfa.definition();
+        let identifier_connections: Vec<&RangeSet<char>> = definition.connections.iter().filter(|connection| connection.source == 1).map(|connection| &connection.label).collect();
+        assert_eq!(identifier_connections, vec![&RangeSet::from_ranges(['0'..='9', 'a'..='z'])]);
+        let mut automaton = definition.build(connection_factory).unwrap();
+        let state = match automaton.run(&mut chars("if")) {
+            AutomatonResult::EmptyIter(state) => state,
+            _ => panic!("keys should be consumed"),
+        };
+        assert!(dfa.states()[state].is_accepting());
+        assert_eq!(dfa.nfa_states(state), &["keyword", "identifier"]);
+        let mut automaton = definition.build(connection_factory).unwrap();
+        assert!(matches!(automaton.run(&mut chars("i-")), AutomatonResult::CouldNotFindNextState(2, _)));
+    }
+}
@@ -0,0 +1,297 @@
+use std::{cell::RefCell, collections::{BTreeMap, HashMap}, hash::Hash, marker::PhantomData, rc::Rc};
+
+use crate::{automaton::NextState, automaton_state::{convert_to_dyn_reference, AutomatonState, SharedAutomatonState}, simple_impl::simple_state::{take_epsilon_connection, ConnectionAction, ConnectionHandle, EpsilonConnection, KeyProvidingData, SimpleInterStateConnection, StateAction}};
+
+/// Map used for finding connections assigned to exact keys.
+pub trait ExactKeyIndex<K, V>: Default {
+    /// Returns value assigned to the key.
+    fn find(&self, key: &K) -> Option<&V>;
+    /// Assigns value to the key. Returns previously assigned value.
+    fn assign(&mut self, key: K, value: V) -> Option<V>;
+    /// Removes value assigned to the key.
+    fn unassign(&mut self, key: &K) -> Option<V>;
+}
+
+impl <K: Hash + Eq, V> ExactKeyIndex<K, V> for HashMap<K, V> {
+    fn find(&self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn assign(&mut self, key: K, value: V) -> Option<V> {
+        self.insert(key, value)
+    }
+
+    fn unassign(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+}
+
+impl <K: Ord, V> ExactKeyIndex<K, V> for BTreeMap<K, V> {
+    fn find(&self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn assign(&mut self, key: K, value: V) -> Option<V> {
+        self.insert(key, value)
+    }
+
+    fn unassign(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+}
+
+/// Indexed state storing exact key connections in a `HashMap`.
+pub type HashIndexedState<'a, K, Id, D, E> = IndexedStateImplementation<'a, K, Id, D, E, HashMap<K, ExactConnection<'a, K, Id, D, E>>>;
+
+/// Indexed state storing exact key connections in a `BTreeMap`.
+pub type OrdIndexedState<'a, K, Id, D, E> = IndexedStateImplementation<'a, K, Id, D, E, BTreeMap<K, ExactConnection<'a, K, Id, D, E>>>;
+
+/// Operation and target state of a connection that doesn't need a matcher - a connection assigned to an exact key or a
+/// fallback connection.
+pub struct ExactConnection<'a, K, Id, D, E> where Id: Copy + 'a, K: 'a, D: 'a, E: 'a {
+    exec_function: ConnectionAction<'a, K, D, E>,
+    connected_state: SharedAutomatonState<'a, Id, D, E>,
+}
+
+impl <'a, K, Id, D, E> ExactConnection<'a, K, Id, D, E> where Id: Copy {
+    fn new<FExec: Fn(&mut D, &K) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(exec_function: FExec, next_state: &Rc<RefCell<S>>) -> Self {
+        Self { exec_function: Box::new(exec_function), connected_state: convert_to_dyn_reference(Rc::clone(next_state)) }
+    }
+
+    /// Executes assigned procedure and returns connected state.
+    fn execute(&self, data: &mut D, key: &K) -> Result<SharedAutomatonState<'a, Id, D, E>, E> {
+        (self.exec_function)(data, key)?;
+        Result::Ok(Rc::clone(&self.connected_state))
+    }
+}
+
+/// AutomatonState implementation designed for states with many connections matching single keys (e.g. dispatching opcodes
+/// or keywords). Connections assigned to exact keys are found through a map instead of checking every connection. Keys
+/// that are not assigned to any connection are matched against connections with matchers (by priority and registration order),
+/// the same way as in [`SimpleStateImplementation`](crate::simple_impl::simple_state::SimpleStateImplementation).
+///
+/// Fallback connection, epsilon connections, entry and exit actions work the same way as in
+/// [`SimpleStateImplementation`](crate::simple_impl::simple_state::SimpleStateImplementation). Connections with matchers
+/// can be removed through returned handles, but (unlike in the simple state) can't be replaced or reordered.
+pub struct IndexedStateImplementation<'a, K, Id, D, E, M> where D: KeyProvidingData<K>, Id: Copy, M: ExactKeyIndex<K, ExactConnection<'a, K, Id, D, E>> {
+    _phantom: PhantomData<D>,
+    id: Id,
+    exact_connections: M,
+    next_states: Vec<(ConnectionHandle, SimpleInterStateConnection<'a, K, Id, D, E>)>,
+    next_handle: usize,
+    fallback_connection: Option<ExactConnection<'a, K, Id, D, E>>,
+    epsilon_connections: Vec<EpsilonConnection<'a, Id, D, E>>,
+    entry_action: Option<StateAction<'a, D, E>>,
+    exit_action: Option<StateAction<'a, D, E>>,
+}
+
+impl <'a, K, Id, D, E, M> IndexedStateImplementation<'a, K, Id, D, E, M> where D: KeyProvidingData<K>, Id: Copy, M: ExactKeyIndex<K, ExactConnection<'a, K, Id, D, E>> {
+    /// Creates new indexed state with provided identifier.
+    pub fn new(id: Id) -> Self {
+        Self { _phantom: PhantomData{}, id, exact_connections: M::default(), next_states: Vec::new(), next_handle: 0, fallback_connection: Option::None, epsilon_connections: Vec::new(), entry_action: Option::None, exit_action: Option::None }
+    }
+
+    /// Assigns connection to the key. Procedure will be executed when this connection is matched. Replaces connection
+    /// previously assigned to the same key.
+    pub fn register_exact_connection<FExec: Fn(&mut D, &K) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(&mut self, key: K, exec_function: FExec, next_state: &Rc<RefCell<S>>) {
+        self.exact_connections.assign(key, ExactConnection::new(exec_function, next_state));
+    }
+
+    /// Assigns connection to the key. Does nothing when matched. Replaces connection previously assigned to the same key.
+    pub fn register_exact_connection_no_action<S: AutomatonState<'a, Id, D, E> + 'a>(&mut self, key: K, next_state: &Rc<RefCell<S>>) {
+        self.register_exact_connection(key, |_, _| Result::Ok(()), next_state);
+    }
+
+    /// Removes connection assigned to the key. Returns `false` if no connection was assigned.
+    pub fn remove_exact_connection(&mut self, key: &K) -> bool {
+        self.exact_connections.unassign(key).is_some()
+    }
+
+    /// Adds connection checked for keys that are not assigned to any exact key connection. Connections are checked in
+    /// the order of their priorities, then in the order of registration. Returned handle can be used for removing this
+    /// connection later.
+    pub fn register_connection(&mut self, connection: SimpleInterStateConnection<'a, K, Id, D, E>) -> ConnectionHandle {
+        let handle = ConnectionHandle(self.next_handle);
+        self.next_handle += 1;
+        let position = self.next_states.partition_point(|(_, c)| c.priority() >= connection.priority());
+        self.next_states.insert(position, (handle, connection));
+        handle
+    }
+
+    /// Removes connection. Returns removed connection or `None` if this state doesn't have a connection with given handle.
+    pub fn remove_connection(&mut self, handle: ConnectionHandle) -> Option<SimpleInterStateConnection<'a, K, Id, D, E>> {
+        let position = self.next_states.iter().position(|(h, _)| *h == handle)?;
+        Option::Some(self.next_states.remove(position).1)
+    }
+
+    /// Sets connection that will be used when no connection matches a key. Procedure will be executed when this
+    /// connection is used. Replaces previously set fallback connection.
+    pub fn set_fallback_connection<FExec: Fn(&mut D, &K) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(&mut self, exec_function: FExec, next_state: &Rc<RefCell<S>>) {
+        self.fallback_connection = Option::Some(ExactConnection::new(exec_function, next_state));
+    }
+
+    /// Sets connection that will be used when no connection matches a key. Does nothing when used. Replaces previously
+    /// set fallback connection.
+    pub fn set_fallback_connection_no_action<S: AutomatonState<'a, Id, D, E> + 'a>(&mut self, next_state: &Rc<RefCell<S>>) {
+        self.set_fallback_connection(|_, _| Result::Ok(()), next_state);
+    }
+
+    /// Removes fallback connection. Returns `false` if it wasn't set.
+    pub fn remove_fallback_connection(&mut self) -> bool {
+        self.fallback_connection.take().is_some()
+    }
+
+    /// Adds connection which will be checked before taking a key from the data.
+    pub fn register_epsilon_connection(&mut self, connection: EpsilonConnection<'a, Id, D, E>) {
+        self.epsilon_connections.push(connection);
+    }
+
+    /// Sets operation executed whenever this state becomes active. Replaces previously set entry action.
+    pub fn set_entry_action<FEntry: Fn(&mut D) -> Result<(), E> + 'a>(&mut self, entry_action: FEntry) {
+        self.entry_action = Option::Some(Box::new(entry_action));
+    }
+
+    /// Sets operation executed whenever a connection of this state is matched, before the operation of that connection.
+    /// Replaces previously set exit action.
+    pub fn set_exit_action<FExit: Fn(&mut D) -> Result<(), E> + 'a>(&mut self, exit_action: FExit) {
+        self.exit_action = Option::Some(Box::new(exit_action));
+    }
+
+    /// Executes exit action if it was set.
+    fn execute_exit(&self, data: &mut D) -> Result<(), E> {
+        if let Option::Some(exit_action) = &self.exit_action {
+            exit_action(data)?;
+        }
+        Result::Ok(())
+    }
+}
+
+impl <'a, K, Id, D, E, M> AutomatonState<'a, Id, D, E> for IndexedStateImplementation<'a, K, Id, D, E, M> where D: KeyProvidingData<K>, Id: Copy, M: ExactKeyIndex<K, ExactConnection<'a, K, Id, D, E>> {
+    fn get_id_owned(&self) -> Id {
+        self.id
+    }
+
+    fn get_id(&self) -> &Id {
+        &self.id
+    }
+
+    /// Takes first epsilon connection accepting the data or finds connection assigned to the key popped from key iterator
+    /// or, if there is none, the first connection matching the key (using fallback connection if no connection matches).
+    /// Executes assigned function and returns next state if everything goes alright.
+    fn execute_next_connection(&self, data: &mut D) -> Result<NextState<'a, Id, D, E>, E> {
+        if let Option::Some(epsilon_result) = take_epsilon_connection(&self.epsilon_connections, self.exit_action.as_ref(), data) {
+            return epsilon_result;
+        }
+        let Option::Some(k) = data.next_key() else {
+            return Result::Ok(NextState::ProcessEnded);
+        };
+        if let Option::Some(c) = self.exact_connections.find(&k) {
+            self.execute_exit(data)?;
+            return Result::Ok(NextState::Continue(c.execute(data, &k)?));
+        }
+        let mut exit = |data: &mut D| self.execute_exit(data);
+        if let Option::Some(next_state) = self.next_states.iter().find_map(|(_, c)| c.try_execute(data, &k, &mut exit)) {
+            return Result::Ok(NextState::Continue(next_state?));
+        }
+        if let Option::Some(c) = &self.fallback_connection {
+            self.execute_exit(data)?;
+            return Result::Ok(NextState::Continue(c.execute(data, &k)?));
+        }
+        data.restore_unmatched_key(k);
+        Result::Ok(NextState::NotFound)
+    }
+
+    /// Executes entry action if it was set.
+    fn execute_entry(&self, data: &mut D) -> Result<(), E> {
+        if let Option::Some(entry_action) = &self.entry_action {
+            entry_action(data)?;
+        }
+        Result::Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use crate::{automaton::{Automaton, AutomatonResult}, automaton_state::new_shared_concrete_state, simple_impl::simple_state::{EpsilonConnection, KeyProvidingData, SimpleInterStateConnection}};
+
+    use super::{HashIndexedState, OrdIndexedState};
+
+    struct Opcodes {
+        keys: VecDeque<u16>,
+        sum: u64,
+    }
+
+    impl KeyProvidingData<u16> for Opcodes {
+        fn next_key(&mut self) -> Option<u16> {
+            self.keys.pop_front()
+        }
+    }
+
+    fn add_opcode(d: &mut Opcodes, k: &u16) -> Result<(), String> {
+        d.sum += u64::from(*k);
+        Result::Ok(())
+    }
+
+    #[test]
+    fn indexed_state_prefers_exact_connections() {
+        let mut data = Opcodes { keys: VecDeque::from(vec![1, 2, 500, 3, 1000]), sum: 0 };
+        let mut automaton: Automaton<u8, Opcodes, String> = Automaton::new(|| {
+            let state = new_shared_concrete_state(HashIndexedState::new(1));
+            state.borrow_mut().register_exact_connection(1, add_opcode, &state);
+            state.borrow_mut().register_exact_connection_no_action(2, &state);
+            state.borrow_mut().register_exact_connection(3, add_opcode, &state);
+            state.borrow_mut().register_connection(SimpleInterStateConnection::new(|k| *k < 1000, |d: &mut Opcodes, _| {
+                d.sum += 10000;
+                Result::Ok(())
+            }, &state));
+            assert!(state.borrow_mut().remove_exact_connection(&3));
+            state
+        });
+        let result = automaton.run(&mut data);
+        assert!(matches!(result, AutomatonResult::CouldNotFindNextState(1, _)));
+        assert_eq!(data.sum, 20001);
+    }
+
+    #[test]
+    fn ordered_indexed_state_works() {
+        let mut data = Opcodes { keys: VecDeque::from(vec![7, 8]), sum: 0 };
+        let mut automaton: Automaton<u8, Opcodes, String> = Automaton::new(|| {
+            let state = new_shared_concrete_state(OrdIndexedState::new(1));
+            state.borrow_mut().register_exact_connection(7, add_opcode, &state);
+            state.borrow_mut().register_exact_connection(8, add_opcode, &state);
+            state
+        });
+        assert!(matches!(automaton.run(&mut data), AutomatonResult::EmptyIter(1)));
+        assert_eq!(data.sum, 15);
+    }
+
+    #[test]
+    fn indexed_state_supports_simple_state_features() {
+        let mut data = Opcodes { keys: VecDeque::from(vec![1, 5, 2, 1]), sum: 0 };
+        let mut automaton: Automaton<u8, Opcodes, String> = Automaton::new(|| {
+            let setup = new_shared_concrete_state(HashIndexedState::new(0));
+            let state = new_shared_concrete_state(HashIndexedState::new(1));
+            setup.borrow_mut().register_epsilon_connection(EpsilonConnection::new(|d: &mut Opcodes| {
+                d.sum += 1000;
+                Result::Ok(())
+            }, &state));
+            state.borrow_mut().set_entry_action(|d: &mut Opcodes| {
+                d.sum += 100;
+                Result::Ok(())
+            });
+            state.borrow_mut().set_exit_action(|d: &mut Opcodes| {
+                d.sum += 10;
+                Result::Ok(())
+            });
+            state.borrow_mut().register_exact_connection(1, add_opcode, &state);
+            let removed = state.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k| *k == 5, &state));
+            assert!(state.borrow_mut().remove_connection(removed).is_some());
+            state.borrow_mut().set_fallback_connection_no_action(&state);
+            setup
+        });
+        assert!(matches!(automaton.run(&mut data), AutomatonResult::EmptyIter(1)));
+        assert_eq!(data.sum, 1000 + 4 * 110 + 100 + 2);
+    }
+}
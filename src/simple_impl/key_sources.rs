@@ -0,0 +1,570 @@
+use std::{borrow::{Borrow, BorrowMut}, cell::RefCell, collections::VecDeque, io::{self, BufRead, BufReader, ErrorKind, Read}, iter::Copied, rc::Rc, slice, str::{CharIndices, Chars}};
+
+use crate::{automaton::{Automaton, AutomatonResult}, automaton_state::AutomatonState, key_provider::{KeyProvidingData, PeekingKeyProvidingData, PushbackKeyProvidingData}, simple_impl::{matchers::Matcher, range_set::{RangeKey, RangeSet}, simple_state::{EpsilonConnection, SimpleInterStateConnection, SimpleStateImplementation}}};
+
+/// Provides keys from an iterator. Keys which were peeked are buffered until they are consumed, so any number of keys
+/// can be looked ahead. Keys pushed back are stored in the same buffer.
+pub struct PeekableKeys<I> where I: Iterator {
+    iter: I,
+    buffer: VecDeque<I::Item>,
+}
+
+impl <I> PeekableKeys<I> where I: Iterator {
+    /// Creates key source providing keys returned by given iterator.
+    pub fn new(iter: I) -> Self {
+        Self { iter, buffer: VecDeque::new() }
+    }
+
+    /// Returns iterator providing the keys (e.g. for checking whether a reader has failed).
+    pub fn get_ref(&self) -> &I {
+        &self.iter
+    }
+}
+
+impl <I> KeyProvidingData<I::Item> for PeekableKeys<I> where I: Iterator {
+    fn next_key(&mut self) -> Option<I::Item> {
+        self.buffer.pop_front().or_else(|| self.iter.next())
+    }
+
+    fn restore_unmatched_key(&mut self, key: I::Item) -> bool {
+        self.buffer.push_front(key);
+        true
+    }
+}
+
+impl <I> PeekingKeyProvidingData<I::Item> for PeekableKeys<I> where I: Iterator, I::Item: Clone {
+    fn peek_key(&mut self, offset: usize) -> Option<I::Item> {
+        while self.buffer.len() <= offset {
+            self.buffer.push_back(self.iter.next()?);
+        }
+        self.buffer.get(offset).cloned()
+    }
+}
+
+impl <I> PushbackKeyProvidingData<I::Item> for PeekableKeys<I> where I: Iterator {
+    fn push_back_key(&mut self, key: I::Item) {
+        self.buffer.push_front(key);
+    }
+}
+
+/// Creates key source providing items of any iterator.
+pub fn keys<I: IntoIterator>(iter: I) -> PeekableKeys<I::IntoIter> {
+    PeekableKeys::new(iter.into_iter())
+}
+
+/// Creates key source providing characters of the text.
+pub fn chars(text: &str) -> PeekableKeys<Chars<'_>> {
+    PeekableKeys::new(text.chars())
+}
+
+/// Creates key source providing characters of the text along with their byte offsets.
+pub fn char_indices(text: &str) -> PeekableKeys<CharIndices<'_>> {
+    PeekableKeys::new(text.char_indices())
+}
+
+/// Creates key source providing bytes of the slice.
+pub fn bytes(bytes: &[u8]) -> PeekableKeys<Copied<slice::Iter<'_, u8>>> {
+    PeekableKeys::new(bytes.iter().copied())
+}
+
+/// Creates key source providing bytes read (with buffering) from the reader.
+pub fn read_bytes<R: Read>(reader: R) -> PeekableKeys<ReadBytes<BufReader<R>>> {
+    PeekableKeys::new(ReadBytes::new(BufReader::new(reader)))
+}
+
+/// Creates key source providing UTF-8 characters read from the reader.
+pub fn read_chars<R: BufRead>(reader: R) -> PeekableKeys<ReadChars<R>> {
+    PeekableKeys::new(ReadChars::new(reader))
+}
+
+/// Iterator over bytes of a reader. Keys can't carry errors, so an error ends the iteration and is stored for later
+/// inspection (interrupted reads are retried).
+pub struct ReadBytes<R> {
+    reader: R,
+    error: Option<io::Error>,
+}
+
+impl <R: BufRead> ReadBytes<R> {
+    /// Creates iterator over bytes of the reader.
+    pub fn new(reader: R) -> Self {
+        Self { reader, error: Option::None }
+    }
+
+    /// Returns error which ended the iteration. Returns `None` if the reader didn't fail.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+}
+
+impl <R: BufRead> Iterator for ReadBytes<R> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.error.is_some() {
+            return Option::None;
+        }
+        loop {
+            match self.reader.fill_buf() {
+                Ok(buffer) => {
+                    let byte = *buffer.first()?;
+                    self.reader.consume(1);
+                    return Option::Some(byte);
+                },
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    self.error = Option::Some(err);
+                    return Option::None;
+                },
+            }
+        }
+    }
+}
+
+/// Iterator over UTF-8 characters of a reader. Invalid or truncated characters end the iteration with an
+/// `ErrorKind::InvalidData` error, which is stored like the errors of the reader.
+pub struct ReadChars<R> {
+    bytes: ReadBytes<R>,
+}
+
+impl <R: BufRead> ReadChars<R> {
+    /// Creates iterator over characters of the reader.
+    pub fn new(reader: R) -> Self {
+        Self { bytes: ReadBytes::new(reader) }
+    }
+
+    /// Returns error which ended the iteration. Returns `None` if the reader didn't fail and the input was valid.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.bytes.error()
+    }
+
+    fn fail(&mut self, message: &str) -> Option<char> {
+        self.bytes.error = Option::Some(io::Error::new(ErrorKind::InvalidData, message));
+        Option::None
+    }
+}
+
+impl <R: BufRead> Iterator for ReadChars<R> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let first = self.bytes.next()?;
+        let width = match first {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return self.fail("invalid UTF-8 leading byte"),
+        };
+        let mut encoded = [first, 0, 0, 0];
+        for byte in encoded.iter_mut().take(width).skip(1) {
+            match self.bytes.next() {
+                Option::Some(next) => *byte = next,
+                Option::None if self.bytes.error.is_some() => return Option::None,
+                Option::None => return self.fail("truncated UTF-8 character"),
+            }
+        }
+        match std::str::from_utf8(&encoded[..width]) {
+            Ok(text) => text.chars().next(),
+            Err(_) => self.fail("invalid UTF-8 character"),
+        }
+    }
+}
+
+/// Data made of a key source and user data, so operations can work on the data without it having to provide keys.
+/// Keys are taken (and peeked or pushed back, if the source allows it) from the `keys` field.
+///
+/// User data can be either owned by the wrapper (see [`Automaton::run_with_keys`]) or borrowed from the caller
+/// (`KeyedData<S, &mut T>`, for automata created for a single run). Constructors with the `_on_data` suffix (e.g. [`SimpleInterStateConnection::new_on_data`],
+/// [`EpsilonConnection::new_on_data`] and [`SimpleStateImplementation::set_entry_action_on_data`]) pass only the user
+/// data to operations and guards in both modes, constructors without an operation work on any data unchanged. Key
+/// sources are also implemented for boxes and mutable references, so graph built for `Box<dyn KeyProvidingData<K>>`
+/// (or for a generic source) can be used with keys coming from anywhere.
+pub struct KeyedData<S, T> {
+    pub keys: S,
+    pub data: T,
+}
+
+impl <S, T> KeyedData<S, T> {
+    /// Combines key source with user data.
+    pub fn new(keys: S, data: T) -> Self {
+        Self { keys, data }
+    }
+}
+
+impl <K, S: KeyProvidingData<K>, T> KeyProvidingData<K> for KeyedData<S, T> {
+    fn next_key(&mut self) -> Option<K> {
+        self.keys.next_key()
+    }
+
+    fn restore_unmatched_key(&mut self, key: K) -> bool {
+        self.keys.restore_unmatched_key(key)
+    }
+}
+
+impl <K, S: PeekingKeyProvidingData<K>, T> PeekingKeyProvidingData<K> for KeyedData<S, T> {
+    fn peek_key(&mut self, offset: usize) -> Option<K> {
+        self.keys.peek_key(offset)
+    }
+}
+
+impl <K, S: PushbackKeyProvidingData<K>, T> PushbackKeyProvidingData<K> for KeyedData<S, T> {
+    fn push_back_key(&mut self, key: K) {
+        self.keys.push_back_key(key);
+    }
+}
+
+impl <'a, Id, S, T, E> Automaton<'a, Id, KeyedData<S, T>, E> {
+    /// Starts automaton with keys taken from a separate source. Data is moved into the run and returned along with the
+    /// result, so the same automaton can be run again on other data and keys.
+    ///
+    /// Automata working on borrowed data (`KeyedData<S, &mut T>`) can be started the same way, but the borrow is a part
+    /// of their type. Data stays borrowed as long as the automaton exists, so such automaton runs on it only once and
+    /// has to be created again (e.g. with a function building the graph) for the next run:
+    ///
+    /// ```compile_fail,E0499
+    /// use automata_like_programming::{
+    ///         automaton::Automaton,
+    ///         automaton_state::new_shared_concrete_state,
+    ///         simple_impl::{key_sources::{chars, KeyedData, PeekableKeys}, simple_state::SimpleStateImplementation}
+    /// };
+    /// use std::str::Chars;
+    ///
+    /// let mut count = 0usize;
+    /// let mut automaton: Automaton<u8, KeyedData<PeekableKeys<Chars>, &mut usize>, String> =
+    ///     Automaton::new(|| new_shared_concrete_state(SimpleStateImplementation::<char, _, _, _>::new(0)));
+    /// automaton.run_with_keys(&mut count, chars("a"));
+    /// // `count` is still borrowed by the automaton.
+    /// automaton.run_with_keys(&mut count, chars("b"));
+    /// ```
+    pub fn run_with_keys(&mut self, data: T, keys: S) -> (AutomatonResult<Id, E>, T) {
+        let mut keyed = KeyedData::new(keys, data);
+        let result = self.run(&mut keyed);
+        (result, keyed.data)
+    }
+}
+
+fn user_data<U: ?Sized, S, T: BorrowMut<U>>(keyed: &mut KeyedData<S, T>) -> &mut U {
+    keyed.data.borrow_mut()
+}
+
+fn user_data_ref<U: ?Sized, S, T: Borrow<U>>(keyed: &KeyedData<S, T>) -> &U {
+    keyed.data.borrow()
+}
+
+impl <'a, K, Id, S, T, E> SimpleInterStateConnection<'a, K, Id, KeyedData<S, T>, E> where Id: Copy {
+    /// Creates new connection with specified matcher and a procedure that will be executed on the user data (without
+    /// the key source) when this connection is matched.
+    pub fn new_on_data<U: ?Sized, M: Fn(&K) -> bool + 'a, FExec: Fn(&mut U, &K) -> Result<(), E> + 'a, St: AutomatonState<'a, Id, KeyedData<S, T>, E> + 'a>(matcher: M, exec_function: FExec, next_state: &Rc<RefCell<St>>) -> Self
+    where T: BorrowMut<U> {
+        Self::new(matcher, move |keyed: &mut KeyedData<S, T>, key: &K| exec_function(user_data(keyed), key), next_state)
+    }
+
+    /// Creates new connection with guard receiving the user data and a procedure that will be executed on the user data
+    /// when this connection is matched.
+    pub fn new_guarded_on_data<U: ?Sized, G: Fn(&U, &K) -> bool + 'a, FExec: Fn(&mut U, &K) -> Result<(), E> + 'a, St: AutomatonState<'a, Id, KeyedData<S, T>, E> + 'a>(guard: G, exec_function: FExec, next_state: &Rc<RefCell<St>>) -> Self
+    where T: BorrowMut<U> {
+        Self::new_guarded(move |keyed: &KeyedData<S, T>, key: &K| guard(user_data_ref(keyed), key), move |keyed: &mut KeyedData<S, T>, key: &K| exec_function(user_data(keyed), key), next_state)
+    }
+
+    /// Creates new connection with guard receiving the user data. Does nothing when matched.
+    pub fn new_guarded_no_action_on_data<U: ?Sized, G: Fn(&U, &K) -> bool + 'a, St: AutomatonState<'a, Id, KeyedData<S, T>, E> + 'a>(guard: G, next_state: &Rc<RefCell<St>>) -> Self
+    where T: Borrow<U> {
+        Self::new_guarded_no_action(move |keyed: &KeyedData<S, T>, key: &K| guard(user_data_ref(keyed), key), next_state)
+    }
+
+    /// Creates new connection matching given sequence of keys and a procedure that will be executed on the user data when
+    /// this connection is matched. See [`SimpleInterStateConnection::new_sequence`].
+    pub fn new_sequence_on_data<U: ?Sized, FExec: Fn(&mut U, &K) -> Result<(), E> + 'a, St: AutomatonState<'a, Id, KeyedData<S, T>, E> + 'a>(sequence: Vec<K>, exec_function: FExec, next_state: &Rc<RefCell<St>>) -> Self
+    where K: PartialEq, S: PushbackKeyProvidingData<K>, T: BorrowMut<U> {
+        Self::new_sequence(sequence, move |keyed: &mut KeyedData<S, T>, key: &K| exec_function(user_data(keyed), key), next_state)
+    }
+
+    /// Creates new connection matching `length` keys with given predicate and a procedure that will be executed on the user
+    /// data when this connection is matched. See [`SimpleInterStateConnection::new_slice`].
+    pub fn new_slice_on_data<U: ?Sized, M: Fn(&[K]) -> bool + 'a, FExec: Fn(&mut U, &K) -> Result<(), E> + 'a, St: AutomatonState<'a, Id, KeyedData<S, T>, E> + 'a>(length: usize, matcher: M, exec_function: FExec, next_state: &Rc<RefCell<St>>) -> Self
+    where K: Clone, S: PushbackKeyProvidingData<K>, T: BorrowMut<U> {
+        Self::new_slice(length, matcher, move |keyed: &mut KeyedData<S, T>, key: &K| exec_function(user_data(keyed), key), next_state)
+    }
+
+    /// Creates new connection matching keys from the set and a procedure that will be executed on the user data when this
+    /// connection is matched.
+    pub fn new_ranges_on_data<U: ?Sized, FExec: Fn(&mut U, &K) -> Result<(), E> + 'a, St: AutomatonState<'a, Id, KeyedData<S, T>, E> + 'a>(ranges: RangeSet<K>, exec_function: FExec, next_state: &Rc<RefCell<St>>) -> Self
+    where K: RangeKey, T: BorrowMut<U> {
+        Self::new_ranges(ranges, move |keyed: &mut KeyedData<S, T>, key: &K| exec_function(user_data(keyed), key), next_state)
+    }
+
+    /// Creates new connection with specified [`Matcher`] and a procedure that will be executed on the user data when this
+    /// connection is matched.
+    pub fn new_matcher_on_data<U: ?Sized, FExec: Fn(&mut U, &K) -> Result<(), E> + 'a, St: AutomatonState<'a, Id, KeyedData<S, T>, E> + 'a>(matcher: Matcher<'a, K>, exec_function: FExec, next_state: &Rc<RefCell<St>>) -> Self
+    where T: BorrowMut<U> {
+        Self::new_matcher(matcher, move |keyed: &mut KeyedData<S, T>, key: &K| exec_function(user_data(keyed), key), next_state)
+    }
+
+    /// Creates new connection with a matcher extracting a value from the key and a procedure that will be executed on the
+    /// user data (receiving the extracted value) when this connection is matched.
+    pub fn new_capturing_on_data<U: ?Sized, V: 'a, M: Fn(&K) -> Option<V> + 'a, FExec: Fn(&mut U, &K, V) -> Result<(), E> + 'a, St: AutomatonState<'a, Id, KeyedData<S, T>, E> + 'a>(matcher: M, exec_function: FExec, next_state: &Rc<RefCell<St>>) -> Self
+    where T: BorrowMut<U> {
+        Self::new_capturing(matcher, move |keyed: &mut KeyedData<S, T>, key: &K, value: V| exec_function(user_data(keyed), key, value), next_state)
+    }
+}
+
+impl <'a, Id, S, T, E> EpsilonConnection<'a, Id, KeyedData<S, T>, E> where Id: Copy {
+    /// Creates new connection that is always taken. Procedure will be executed on the user data while changing state.
+    pub fn new_on_data<U: ?Sized, FExec: Fn(&mut U) -> Result<(), E> + 'a, St: AutomatonState<'a, Id, KeyedData<S, T>, E> + 'a>(exec_function: FExec, next_state: &Rc<RefCell<St>>) -> Self
+    where T: BorrowMut<U> {
+        Self::new(move |keyed: &mut KeyedData<S, T>| exec_function(user_data(keyed)), next_state)
+    }
+
+    /// Creates new connection that is taken only if guard accepts current state of the user data. Procedure will be
+    /// executed on the user data while changing state.
+    pub fn new_guarded_on_data<U: ?Sized, G: Fn(&U) -> bool + 'a, FExec: Fn(&mut U) -> Result<(), E> + 'a, St: AutomatonState<'a, Id, KeyedData<S, T>, E> + 'a>(guard: G, exec_function: FExec, next_state: &Rc<RefCell<St>>) -> Self
+    where T: BorrowMut<U> {
+        Self::new_guarded(move |keyed: &KeyedData<S, T>| guard(user_data_ref(keyed)), move |keyed: &mut KeyedData<S, T>| exec_function(user_data(keyed)), next_state)
+    }
+
+    /// Creates new connection that is taken only if guard accepts current state of the user data. Does nothing when taken.
+    pub fn new_guarded_no_action_on_data<U: ?Sized, G: Fn(&U) -> bool + 'a, St: AutomatonState<'a, Id, KeyedData<S, T>, E> + 'a>(guard: G, next_state: &Rc<RefCell<St>>) -> Self
+    where T: Borrow<U> {
+        Self::new_guarded_no_action(move |keyed: &KeyedData<S, T>| guard(user_data_ref(keyed)), next_state)
+    }
+
+    /// Creates new connection that is taken only if matcher accepts upcoming keys. Procedure will be executed on the user
+    /// data while changing state. See [`EpsilonConnection::new_lookahead`].
+    pub fn new_lookahead_on_data<K: 'a, U: ?Sized, M: Fn(&[K]) -> bool + 'a, FExec: Fn(&mut U) -> Result<(), E> + 'a, St: AutomatonState<'a, Id, KeyedData<S, T>, E> + 'a>(length: usize, matcher: M, exec_function: FExec, next_state: &Rc<RefCell<St>>) -> Self
+    where S: PeekingKeyProvidingData<K>, T: BorrowMut<U> {
+        Self::new_lookahead(length, matcher, move |keyed: &mut KeyedData<S, T>| exec_function(user_data(keyed)), next_state)
+    }
+}
+
+impl <'a, K, Id, S, T, E> SimpleStateImplementation<'a, K, Id, KeyedData<S, T>, E> where S: KeyProvidingData<K>, Id: Copy {
+    /// Sets connection that will be used when no registered connection matches a key. Procedure will be executed on the
+    /// user data when this connection is used. Replaces previously set fallback connection.
+    pub fn set_fallback_connection_on_data<U: ?Sized, FExec: Fn(&mut U, &K) -> Result<(), E> + 'a, St: AutomatonState<'a, Id, KeyedData<S, T>, E> + 'a>(&mut self, exec_function: FExec, next_state: &Rc<RefCell<St>>)
+    where T: BorrowMut<U> {
+        self.set_fallback_connection(move |keyed: &mut KeyedData<S, T>, key: &K| exec_function(user_data(keyed), key), next_state);
+    }
+
+    /// Sets operation executed on the user data whenever this state becomes active. Replaces previously set entry action.
+    pub fn set_entry_action_on_data<U: ?Sized, FEntry: Fn(&mut U) -> Result<(), E> + 'a>(&mut self, entry_action: FEntry)
+    where T: BorrowMut<U> {
+        self.set_entry_action(move |keyed: &mut KeyedData<S, T>| entry_action(user_data(keyed)));
+    }
+
+    /// Sets operation executed on the user data whenever a connection of this state is matched, before the operation of
+    /// that connection. Replaces previously set exit action.
+    pub fn set_exit_action_on_data<U: ?Sized, FExit: Fn(&mut U) -> Result<(), E> + 'a>(&mut self, exit_action: FExit)
+    where T: BorrowMut<U> {
+        self.set_exit_action(move |keyed: &mut KeyedData<S, T>| exit_action(user_data(keyed)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::VecDeque, str::Chars};
+
+    use crate::{automaton::{Automaton, AutomatonResult}, automaton_state::new_shared_concrete_state, simple_impl::simple_state::{EpsilonConnection, KeyProvidingData, PeekingKeyProvidingData, PushbackKeyProvidingData, SimpleInterStateConnection, SimpleStateImplementation}};
+
+    use super::{bytes, char_indices, chars, keys, read_bytes, read_chars, KeyedData, PeekableKeys};
+
+    #[test]
+    fn peekable_keys_buffer_peeked_keys() {
+        let mut keys = PeekableKeys::new("abc".chars());
+        assert_eq!(keys.peek_key(1), Option::Some('b'));
+        assert_eq!(keys.peek_key(0), Option::Some('a'));
+        assert_eq!(keys.next_key(), Option::Some('a'));
+        assert_eq!(keys.peek_key(2), Option::None);
+        assert_eq!(keys.next_key(), Option::Some('b'));
+        assert_eq!(keys.next_key(), Option::Some('c'));
+        assert_eq!(keys.next_key(), Option::None);
+    }
+
+    #[test]
+    fn pushed_back_keys_are_returned_first() {
+        let mut keys = PeekableKeys::new("cd".chars());
+        assert_eq!(keys.next_key(), Option::Some('c'));
+        keys.push_back_key('b');
+        keys.push_back_key('a');
+        assert_eq!(keys.peek_key(2), Option::Some('d'));
+        assert_eq!(keys.next_key(), Option::Some('a'));
+        assert_eq!(keys.next_key(), Option::Some('b'));
+        assert_eq!(keys.next_key(), Option::Some('d'));
+    }
+
+    #[test]
+    fn unmatched_key_is_available_for_next_automaton() {
+        let mut keys = VecDeque::from(vec!['1', '2', 'a', 'b', '3']);
+        let mut digits: Automaton<u8, VecDeque<char>, String> = Automaton::new(|| {
+            let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+            state.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k: &char| k.is_ascii_digit(), &state));
+            state
+        });
+        let mut letters: Automaton<u8, VecDeque<char>, String> = Automaton::new(|| {
+            let state = new_shared_concrete_state(SimpleStateImplementation::new(2));
+            state.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k: &char| k.is_alphabetic(), &state));
+            state
+        });
+        assert!(matches!(digits.run(&mut keys), AutomatonResult::CouldNotFindNextState(1, _)));
+        assert_eq!(keys, vec!['a', 'b', '3']);
+        assert!(matches!(letters.run(&mut keys), AutomatonResult::CouldNotFindNextState(2, _)));
+        assert_eq!(keys, vec!['3']);
+        assert!(matches!(digits.run(&mut keys), AutomatonResult::EmptyIter(1)));
+    }
+
+    struct Tokenizer<I> where I: Iterator<Item = char> {
+        keys: PeekableKeys<I>,
+        tokens: Vec<&'static str>,
+    }
+
+    impl <I> KeyProvidingData<char> for Tokenizer<I> where I: Iterator<Item = char> {
+        fn next_key(&mut self) -> Option<char> {
+            self.keys.next_key()
+        }
+    }
+
+    impl <I> PeekingKeyProvidingData<char> for Tokenizer<I> where I: Iterator<Item = char> {
+        fn peek_key(&mut self, offset: usize) -> Option<char> {
+            self.keys.peek_key(offset)
+        }
+    }
+
+    #[test]
+    fn lookahead_connections_do_not_consume_keys() {
+        type Data = Tokenizer<std::vec::IntoIter<char>>;
+        let mut data = Tokenizer { keys: PeekableKeys::new("<<=<a".chars().collect::<Vec<_>>().into_iter()), tokens: Vec::new() };
+        let mut automaton: Automaton<u8, Data, String> = Automaton::new(|| {
+            let start = new_shared_concrete_state(SimpleStateImplementation::new(0));
+            let less = new_shared_concrete_state(SimpleStateImplementation::new(1));
+            let less_equal = new_shared_concrete_state(SimpleStateImplementation::new(2));
+            start.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k| *k == '<', &less));
+            less.borrow_mut().register_epsilon_connection(EpsilonConnection::new_lookahead_no_action(1, |keys: &[char]| keys == ['='], &less_equal));
+            less.borrow_mut().register_epsilon_connection(EpsilonConnection::new(|d: &mut Data| {
+                d.tokens.push("<");
+                Result::Ok(())
+            }, &start));
+            less_equal.borrow_mut().register_connection(SimpleInterStateConnection::new(|k| *k == '=', |d: &mut Data, _| {
+                d.tokens.push("<=");
+                Result::Ok(())
+            }, &start));
+            start
+        });
+        let result = automaton.run(&mut data);
+        assert!(matches!(result, AutomatonResult::CouldNotFindNextState(0, _)));
+        assert_eq!(data.tokens, vec!["<", "<=", "<"]);
+    }
+
+    #[test]
+    fn ready_made_sources_provide_keys() {
+        assert_eq!(chars("zą").next_key(), Option::Some('z'));
+        let mut indices = char_indices("ąb");
+        assert_eq!(indices.peek_key(1), Option::Some((2, 'b')));
+        assert_eq!(bytes(&[7, 8]).peek_key(1), Option::Some(8));
+        assert_eq!(keys(vec![1, 2, 3]).peek_key(2), Option::Some(3));
+        let mut reader = read_bytes(&b"ab"[..]);
+        assert_eq!((reader.next_key(), reader.next_key(), reader.next_key()), (Option::Some(b'a'), Option::Some(b'b'), Option::None));
+        assert!(reader.get_ref().error().is_none());
+    }
+
+    #[test]
+    fn reader_chars_decode_utf8_and_report_errors() {
+        let mut reader = read_chars("aż€𝄞".as_bytes());
+        let decoded: Vec<char> = std::iter::from_fn(|| reader.next_key()).collect();
+        assert_eq!(decoded, vec!['a', 'ż', '€', '𝄞']);
+        assert!(reader.get_ref().error().is_none());
+
+        let mut invalid = read_chars(&[b'a', 0xC5][..]);
+        assert_eq!(invalid.next_key(), Option::Some('a'));
+        assert_eq!(invalid.next_key(), Option::None);
+        assert_eq!(invalid.get_ref().error().map(|err| err.kind()), Option::Some(std::io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn keyed_data_separates_keys_from_user_data() {
+        type Data<'t> = KeyedData<PeekableKeys<std::str::CharIndices<'t>>, Vec<usize>>;
+        let mut data = KeyedData::new(char_indices("aabbacacaabab"), Vec::new());
+        let mut automaton: Automaton<u8, Data, String> = Automaton::new(|| {
+            let start = new_shared_concrete_state(SimpleStateImplementation::new(0));
+            let a = new_shared_concrete_state(SimpleStateImplementation::new(1));
+            start.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k: &(usize, char)| k.1 == 'a', &a));
+            start.borrow_mut().set_fallback_connection_no_action(&start);
+            a.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k: &(usize, char)| k.1 == 'a', &a));
+            a.borrow_mut().register_connection(SimpleInterStateConnection::new(|k: &(usize, char)| k.1 == 'b', |d: &mut Data, k| {
+                d.data.push(k.0);
+                Result::Ok(())
+            }, &start));
+            a.borrow_mut().set_fallback_connection_no_action(&start);
+            start
+        });
+        assert!(automaton.run(&mut data).is_empty_iter());
+        assert_eq!(data.data, vec![2, 10, 12]);
+    }
+
+    struct Sums {
+        values: Vec<u32>,
+    }
+
+    type SumsData<'a> = KeyedData<Box<dyn KeyProvidingData<char> + 'a>, Sums>;
+
+    fn summing_automaton<'a>() -> Automaton<'a, u8, SumsData<'a>, String> {
+        Automaton::new(|| {
+            let start = new_shared_concrete_state(SimpleStateImplementation::new(0));
+            let number = new_shared_concrete_state(SimpleStateImplementation::new(1));
+            start.borrow_mut().register_connection(SimpleInterStateConnection::new_on_data(|k: &char| k.is_ascii_digit(), |sums: &mut Sums, k: &char| {
+                sums.values.push(k.to_digit(10).unwrap());
+                Result::Ok(())
+            }, &number));
+            number.borrow_mut().register_connection(SimpleInterStateConnection::new_on_data(|k: &char| k.is_ascii_digit(), |sums: &mut Sums, k: &char| {
+                *sums.values.last_mut().unwrap() += k.to_digit(10).unwrap();
+                Result::Ok(())
+            }, &number));
+            number.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k: &char| *k == ',', &start));
+            start
+        })
+    }
+
+    #[test]
+    fn automaton_runs_many_times_with_separate_keys() {
+        let mut automaton = summing_automaton();
+        let (result, sums) = automaton.run_with_keys(Sums { values: vec![0] }, Box::new(chars("12,345")));
+        assert!(matches!(result, AutomatonResult::EmptyIter(1)));
+        let (result, sums) = automaton.run_with_keys(sums, Box::new(VecDeque::from(vec!['9', 'x'])));
+        assert!(matches!(result, AutomatonResult::CouldNotFindNextState(1, _)));
+        assert_eq!(sums.values, vec![0, 3, 12, 9]);
+    }
+
+    #[test]
+    fn state_actions_work_on_owned_and_borrowed_data() {
+        fn build<'a, T: std::borrow::BorrowMut<Vec<String>> + 'a>() -> Automaton<'a, u8, KeyedData<PeekableKeys<Chars<'a>>, T>, String> {
+            Automaton::new(|| {
+                let start = new_shared_concrete_state(SimpleStateImplementation::new(0));
+                let word = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                let end = new_shared_concrete_state(SimpleStateImplementation::new(2));
+                start.borrow_mut().register_epsilon_connection(EpsilonConnection::new_guarded_no_action_on_data(|words: &Vec<String>| words.len() >= 3, &end));
+                start.borrow_mut().register_connection(SimpleInterStateConnection::new_guarded_on_data(|_: &Vec<String>, k: &char| k.is_alphabetic(), |words: &mut Vec<String>, k: &char| {
+                    words.last_mut().unwrap().push(*k);
+                    Result::Ok(())
+                }, &word));
+                start.borrow_mut().set_fallback_connection_no_action(&start);
+                start.borrow_mut().set_entry_action_on_data(|words: &mut Vec<String>| {
+                    words.push(String::new());
+                    Result::Ok(())
+                });
+                word.borrow_mut().register_connection(SimpleInterStateConnection::new_capturing_on_data(|k: &char| k.is_alphabetic().then(|| k.to_ascii_uppercase()), |words: &mut Vec<String>, _: &char, upper: char| {
+                    words.last_mut().unwrap().push(upper);
+                    Result::Ok(())
+                }, &word));
+                word.borrow_mut().set_fallback_connection_on_data(|words: &mut Vec<String>, _: &char| {
+                    words.last_mut().unwrap().push('.');
+                    Result::Ok(())
+                }, &start);
+                start
+            })
+        }
+        let mut owned = KeyedData::new(chars("ab cd "), Vec::new());
+        assert!(matches!(build().run(&mut owned), AutomatonResult::EmptyIter(2)));
+        assert_eq!(owned.data, vec!["aB.", "cD.", ""]);
+        // Automaton working on borrowed data keeps the borrow, so it's created for a single run.
+        let mut words = Vec::new();
+        assert!(matches!(build().run_with_keys(&mut words, chars("x")).0, AutomatonResult::EmptyIter(1)));
+        assert_eq!(words, vec!["x"]);
+    }
+}
@@ -0,0 +1,170 @@
+/// Declares a graph made of [`SimpleStateImplementation`](crate::simple_impl::simple_state::SimpleStateImplementation)
+/// states in a single block and returns its start state. All states are created before any connection is registered, so
+/// connections can point to states declared later in the block as well as to their own source state.
+///
+/// Block consists of:
+/// * `states { name = id, ... }` - Declares states with their identifiers. Names can be used in the rest of the block
+///   only, since the macro expands to a block expression and they aren't visible in the surrounding code.
+/// * `start name;` - State that will be returned (the automaton will start on it).
+/// * Transitions in one of the forms below. Transitions are registered in the order of declaration, so the first one
+///   matching a key will be used.
+///     * `source --'x'--> target;` - Matches keys equal to given literal (alternatives can be separated with `|`).
+///     * `source --[matcher]--> target;` - Matches keys using given `Fn(&K) -> bool`.
+///     * `source --_--> target;` - Sets fallback connection which is used when no other connection of the source state
+///       matches a key (regardless of where it was declared).
+///     * Both forms can be followed by `=> action` where `action` is the `Fn(&mut D, &K) -> Result<(), E>` which
+///       will be executed while changing state.
+///
+/// # Example of automaton finding "ab" pattern
+///
+/// ```
+/// use automata_like_programming::{
+///         automaton::Automaton,
+///         simple_graph,
+///         simple_impl::simple_state::KeyProvidingData
+/// };
+///
+/// struct TextMatching {
+///     chars: Vec<char>,
+///     matches: Vec<usize>,
+///     iter: usize,
+/// }
+///
+/// impl KeyProvidingData<(usize, char)> for TextMatching {
+///     fn next_key(&mut self) -> Option<(usize, char)> {
+///         let key = self.chars.get(self.iter).map(|c| (self.iter, *c));
+///         self.iter += 1;
+///         key
+///     }
+/// }
+///
+/// let mut matching_data = TextMatching { chars: "aabbacacaabab".chars().collect(), matches: Vec::new(), iter: 0 };
+/// let mut automaton: Automaton<u32, TextMatching, String> = Automaton::new(|| simple_graph! {
+///     states {
+///         non_match = 0,
+///         a = 1,
+///         b = 2,
+///     }
+///     start non_match;
+///     non_match --[|k: &(usize, char)| k.1 == 'a']--> a;
+///     non_match --_--> non_match;
+///     a --[|k: &(usize, char)| k.1 == 'a']--> a;
+///     a --[|k: &(usize, char)| k.1 == 'b']--> b => |data: &mut TextMatching, key: &(usize, char)| {
+///         data.matches.push(key.0);
+///         Result::Ok(())
+///     };
+///     a --_--> non_match;
+///     b --[|k: &(usize, char)| k.1 == 'a']--> a;
+///     b --_--> non_match;
+/// });
+/// let result = automaton.run(&mut matching_data);
+/// assert!(result.is_empty_iter());
+/// // Positions of the "b" character of each match.
+/// assert_eq!(matching_data.matches, vec![2, 10, 12]);
+/// ```
+#[macro_export]
+macro_rules! simple_graph {
+    (states { $($state:ident = $id:expr),* $(,)? } start $start:ident; $($source:ident -- $first:tt $(| $alternative:tt)* --> $target:ident $(=> $action:expr)?;)*) => {{
+        $(
+            let $state = $crate::automaton_state::new_shared_concrete_state(
+                $crate::simple_impl::simple_state::SimpleStateImplementation::new($id)
+            );
+        )*
+        $(
+            $crate::simple_graph!(@connection $source, $target, [$first $(| $alternative)*], $($action)?);
+        )*
+        $start
+    }};
+    (@connection $source:ident, $target:ident, [_], $action:expr) => {
+        $source.borrow_mut().set_fallback_connection($action, &$target);
+    };
+    (@connection $source:ident, $target:ident, [_], ) => {
+        $source.borrow_mut().set_fallback_connection_no_action(&$target);
+    };
+    (@connection $source:ident, $target:ident, [[$matcher:expr]], $action:expr) => {
+        $source.borrow_mut().register_connection(
+            $crate::simple_impl::simple_state::SimpleInterStateConnection::new($matcher, $action, &$target)
+        );
+    };
+    (@connection $source:ident, $target:ident, [[$matcher:expr]], ) => {
+        $source.borrow_mut().register_connection(
+            $crate::simple_impl::simple_state::SimpleInterStateConnection::new_no_action($matcher, &$target)
+        );
+    };
+    (@connection $source:ident, $target:ident, [$($key:literal)|+], $action:expr) => {
+        $source.borrow_mut().register_connection(
+            $crate::simple_impl::simple_state::SimpleInterStateConnection::new(|k| $(*k == $key)||+, $action, &$target)
+        );
+    };
+    (@connection $source:ident, $target:ident, [$($key:literal)|+], ) => {
+        $source.borrow_mut().register_connection(
+            $crate::simple_impl::simple_state::SimpleInterStateConnection::new_no_action(|k| $(*k == $key)||+, &$target)
+        );
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use crate::{automaton::{Automaton, AutomatonResult}, simple_impl::key_sources::KeyedData};
+
+    type TestData = KeyedData<VecDeque<u8>, String>;
+
+    #[test]
+    fn simple_graph_handles_forward_references_and_self_loops() {
+        let mut data = KeyedData::new(VecDeque::from(vec![1, 1, 2, 3, 4, 9, 0, 0]), String::new());
+        let mut automaton: Automaton<u8, TestData, String> = Automaton::new(|| crate::simple_graph! {
+            states {
+                first = 1,
+                second = 2,
+            }
+            start first;
+            first --1--> first => |d: &mut TestData, _: &u8| {
+                d.data.push('1');
+                Result::Ok(())
+            };
+            first --2 | 3--> second;
+            second --[|k: &u8| *k > 2]--> second => |d: &mut TestData, k: &u8| {
+                d.data.push_str(&k.to_string());
+                Result::Ok(())
+            };
+            second --_--> first => |d: &mut TestData, _: &u8| {
+                d.data.push('_');
+                Result::Ok(())
+            };
+        });
+        let result = automaton.run(&mut data);
+        assert!(matches!(result, AutomatonResult::CouldNotFindNextState(1, _)));
+        assert_eq!(data.data, "11349_");
+    }
+
+    #[test]
+    fn simple_graph_accepts_more_transitions_than_recursion_limit() {
+        let mut data = KeyedData::new(VecDeque::from(vec![3, 149, 150]), String::new());
+        let mut automaton: Automaton<u8, TestData, String> = Automaton::new(|| crate::simple_graph! {
+            states {
+                first = 1,
+            }
+            start first;
+            first --0--> first; first --1--> first; first --2--> first; first --3--> first; first --4--> first; first --5--> first; first --6--> first; first --7--> first; first --8--> first; first --9--> first;
+            first --10--> first; first --11--> first; first --12--> first; first --13--> first; first --14--> first; first --15--> first; first --16--> first; first --17--> first; first --18--> first; first --19--> first;
+            first --20--> first; first --21--> first; first --22--> first; first --23--> first; first --24--> first; first --25--> first; first --26--> first; first --27--> first; first --28--> first; first --29--> first;
+            first --30--> first; first --31--> first; first --32--> first; first --33--> first; first --34--> first; first --35--> first; first --36--> first; first --37--> first; first --38--> first; first --39--> first;
+            first --40--> first; first --41--> first; first --42--> first; first --43--> first; first --44--> first; first --45--> first; first --46--> first; first --47--> first; first --48--> first; first --49--> first;
+            first --50--> first; first --51--> first; first --52--> first; first --53--> first; first --54--> first; first --55--> first; first --56--> first; first --57--> first; first --58--> first; first --59--> first;
+            first --60--> first; first --61--> first; first --62--> first; first --63--> first; first --64--> first; first --65--> first; first --66--> first; first --67--> first; first --68--> first; first --69--> first;
+            first --70--> first; first --71--> first; first --72--> first; first --73--> first; first --74--> first; first --75--> first; first --76--> first; first --77--> first; first --78--> first; first --79--> first;
+            first --80--> first; first --81--> first; first --82--> first; first --83--> first; first --84--> first; first --85--> first; first --86--> first; first --87--> first; first --88--> first; first --89--> first;
+            first --90--> first; first --91--> first; first --92--> first; first --93--> first; first --94--> first; first --95--> first; first --96--> first; first --97--> first; first --98--> first; first --99--> first;
+            first --100--> first; first --101--> first; first --102--> first; first --103--> first; first --104--> first; first --105--> first; first --106--> first; first --107--> first; first --108--> first; first --109--> first;
+            first --110--> first; first --111--> first; first --112--> first; first --113--> first; first --114--> first; first --115--> first; first --116--> first; first --117--> first; first --118--> first; first --119--> first;
+            first --120--> first; first --121--> first; first --122--> first; first --123--> first; first --124--> first; first --125--> first; first --126--> first; first --127--> first; first --128--> first; first --129--> first;
+            first --130--> first; first --131--> first; first --132--> first; first --133--> first; first --134--> first; first --135--> first; first --136--> first; first --137--> first; first --138--> first; first --139--> first;
+            first --140--> first; first --141--> first; first --142--> first; first --143--> first; first --144--> first; first --145--> first; first --146--> first; first --147--> first; first --148--> first; first --149--> first;
+        });
+        let result = automaton.run(&mut data);
+        assert!(matches!(result, AutomatonResult::CouldNotFindNextState(1, _)));
+        assert_eq!(data.keys, [150]);
+    }
+}
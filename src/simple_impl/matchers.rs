@@ -0,0 +1,194 @@
+use std::{fmt::{Debug, Display}, ops::RangeInclusive};
+
+use crate::simple_impl::simple_state::KeyMatcher;
+
+#[cfg(feature = "unicode")]
+pub use unicode_general_category::GeneralCategory;
+
+/// Predicate deciding whether a connection should be chosen for a key, along with a human-readable description of the
+/// keys it accepts. Matchers can be combined with [`not`], [`and`], [`or`] and [`on`], and used with
+/// [`SimpleInterStateConnection::new_matcher`](crate::simple_impl::simple_state::SimpleInterStateConnection::new_matcher).
+pub struct Matcher<'a, K> {
+    predicate: KeyMatcher<'a, K>,
+    description: String,
+}
+
+impl <'a, K> Matcher<'a, K> {
+    /// Creates matcher from a predicate and its description.
+    pub fn new<P: Fn(&K) -> bool + 'a, S: Into<String>>(description: S, predicate: P) -> Self {
+        Self { predicate: Box::new(predicate), description: description.into() }
+    }
+
+    /// Checks whether the key is accepted.
+    pub fn matches(&self, key: &K) -> bool {
+        (self.predicate)(key)
+    }
+
+    /// Returns description of accepted keys.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Converts matcher into a plain predicate (e.g. for the `simple_graph!` macro). Description is lost.
+    pub fn into_fn(self) -> impl Fn(&K) -> bool + 'a where K: 'a {
+        move |key| self.matches(key)
+    }
+}
+
+impl <K> Debug for Matcher<'_, K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Matcher").field(&self.description).finish()
+    }
+}
+
+impl <K> Display for Matcher<'_, K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.description)
+    }
+}
+
+/// Accepts every key.
+pub fn any<'a, K>() -> Matcher<'a, K> {
+    Matcher::new("any key", |_| true)
+}
+
+/// Accepts keys equal to provided value.
+pub fn eq<'a, K: PartialEq + Debug + 'a>(expected: K) -> Matcher<'a, K> {
+    Matcher::new(format!("{:?}", expected), move |key| *key == expected)
+}
+
+/// Accepts keys equal to any of provided values.
+pub fn one_of<'a, K: PartialEq + Debug + 'a, I: IntoIterator<Item = K>>(expected: I) -> Matcher<'a, K> {
+    let expected: Vec<K> = expected.into_iter().collect();
+    Matcher::new(format!("one of {:?}", expected), move |key| expected.contains(key))
+}
+
+/// Accepts keys within provided range.
+pub fn range<'a, K: PartialOrd + Debug + 'a>(range: RangeInclusive<K>) -> Matcher<'a, K> {
+    Matcher::new(format!("{:?}", range), move |key| range.contains(key))
+}
+
+/// Accepts keys rejected by provided matcher.
+pub fn not<'a, K: 'a>(matcher: Matcher<'a, K>) -> Matcher<'a, K> {
+    Matcher::new(format!("not ({})", matcher.description), move |key| !matcher.matches(key))
+}
+
+/// Accepts keys accepted by both matchers.
+pub fn and<'a, K: 'a>(first: Matcher<'a, K>, second: Matcher<'a, K>) -> Matcher<'a, K> {
+    Matcher::new(format!("({}) and ({})", first.description, second.description), move |key| first.matches(key) && second.matches(key))
+}
+
+/// Accepts keys accepted by any of the matchers.
+pub fn or<'a, K: 'a>(first: Matcher<'a, K>, second: Matcher<'a, K>) -> Matcher<'a, K> {
+    Matcher::new(format!("({}) or ({})", first.description, second.description), move |key| first.matches(key) || second.matches(key))
+}
+
+/// Accepts keys for which the projected value is accepted by provided matcher. Designed for keys carrying additional
+/// information, e.g. `on(|k: &(usize, char)| k.1, eq('a'))` for keys made of positions and characters.
+pub fn on<'a, K, T: 'a, P: Fn(&K) -> T + 'a>(projection: P, matcher: Matcher<'a, T>) -> Matcher<'a, K> {
+    Matcher::new(format!("projection {}", matcher.description), move |key| matcher.matches(&projection(key)))
+}
+
+/// Accepts alphabetic characters (Unicode `Alphabetic` property).
+pub fn alphabetic<'a>() -> Matcher<'a, char> {
+    Matcher::new("alphabetic", |key: &char| key.is_alphabetic())
+}
+
+/// Accepts alphabetic and numeric characters.
+pub fn alphanumeric<'a>() -> Matcher<'a, char> {
+    Matcher::new("alphanumeric", |key: &char| key.is_alphanumeric())
+}
+
+/// Accepts ASCII digits (`'0'..='9'`).
+pub fn digit<'a>() -> Matcher<'a, char> {
+    Matcher::new("digit", |key: &char| key.is_ascii_digit())
+}
+
+/// Accepts numeric characters (Unicode general categories `Nd`, `Nl` and `No`).
+pub fn numeric<'a>() -> Matcher<'a, char> {
+    Matcher::new("numeric", |key: &char| key.is_numeric())
+}
+
+/// Accepts whitespace characters (Unicode `White_Space` property).
+pub fn whitespace<'a>() -> Matcher<'a, char> {
+    Matcher::new("whitespace", |key: &char| key.is_whitespace())
+}
+
+/// Accepts lowercase characters (Unicode `Lowercase` property).
+pub fn lowercase<'a>() -> Matcher<'a, char> {
+    Matcher::new("lowercase", |key: &char| key.is_lowercase())
+}
+
+/// Accepts uppercase characters (Unicode `Uppercase` property).
+pub fn uppercase<'a>() -> Matcher<'a, char> {
+    Matcher::new("uppercase", |key: &char| key.is_uppercase())
+}
+
+/// Accepts characters belonging to any of provided Unicode general categories. Requires the `unicode` feature.
+#[cfg(feature = "unicode")]
+pub fn general_category<'a, I: IntoIterator<Item = GeneralCategory>>(categories: I) -> Matcher<'a, char> {
+    let categories: Vec<GeneralCategory> = categories.into_iter().collect();
+    let names: Vec<&str> = categories.iter().map(|category| category.abbreviation()).collect();
+    Matcher::new(format!("general category {}", names.join("|")), move |key: &char| categories.contains(&unicode_general_category::get_general_category(*key)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use crate::{automaton::{Automaton, AutomatonResult}, automaton_state::new_shared_concrete_state, simple_impl::{key_sources::KeyedData, simple_state::{SimpleInterStateConnection, SimpleStateImplementation}}};
+
+    use super::{alphabetic, and, any, digit, eq, not, on, one_of, or, range, whitespace};
+
+    #[test]
+    fn matchers_combine_and_describe_themselves() {
+        let identifier = or(alphabetic(), eq('_'));
+        assert!(identifier.matches(&'ż') && identifier.matches(&'_') && !identifier.matches(&'1'));
+        assert_eq!(identifier.description(), "(alphabetic) or ('_')");
+        let hex = or(digit(), range('a'..='f'));
+        assert!(hex.matches(&'c') && !hex.matches(&'g'));
+        let operator = and(one_of(['+', '-', '*', '/']), not(eq('/')));
+        assert!(operator.matches(&'-') && !operator.matches(&'/'));
+        assert_eq!(operator.to_string(), "(one of ['+', '-', '*', '/']) and (not ('/'))");
+        assert!(any::<u8>().matches(&7));
+        let positioned = on(|k: &(usize, char)| k.1, whitespace());
+        assert!(positioned.matches(&(3, ' ')) && !positioned.matches(&(3, 'x')));
+        assert_eq!(format!("{:?}", positioned), "Matcher(\"projection whitespace\")");
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn general_category_matcher_uses_unicode_tables() {
+        use super::{general_category, GeneralCategory};
+
+        let letters = general_category([GeneralCategory::UppercaseLetter, GeneralCategory::TitlecaseLetter]);
+        assert!(letters.matches(&'Ł') && letters.matches(&'ǅ') && !letters.matches(&'ł'));
+        assert_eq!(letters.description(), "general category Lu|Lt");
+    }
+
+    type Words = KeyedData<VecDeque<char>, Vec<String>>;
+
+    #[test]
+    fn matcher_connections_are_described() {
+        let mut data = KeyedData::new("ab cd  e".chars().collect(), Vec::new());
+        let mut automaton: Automaton<u8, Words, String> = Automaton::new(|| {
+            let space = new_shared_concrete_state(SimpleStateImplementation::new(1));
+            let word = new_shared_concrete_state(SimpleStateImplementation::new(2));
+            let push_char = |d: &mut Words, k: &char| {
+                d.data.last_mut().unwrap().push(*k);
+                Result::Ok(())
+            };
+            space.borrow_mut().register_connection(SimpleInterStateConnection::new_matcher(whitespace(), |_: &mut Words, _| Result::Ok(()), &space));
+            space.borrow_mut().register_connection(SimpleInterStateConnection::new_matcher(alphabetic(), |d: &mut Words, k: &char| {
+                d.data.push(k.to_string());
+                Result::Ok(())
+            }, &word));
+            word.borrow_mut().register_connection(SimpleInterStateConnection::new_matcher(alphabetic(), push_char, &word));
+            word.borrow_mut().register_connection(SimpleInterStateConnection::new_matcher_no_action(whitespace(), &space));
+            assert_eq!(word.borrow().describe_evaluation_order(), "state 2\n  ConnectionHandle(0) priority 0 key alphabetic -> 2\n  ConnectionHandle(1) priority 0 key whitespace -> 1\n");
+            space
+        });
+        assert!(matches!(automaton.run(&mut data), AutomatonResult::EmptyIter(2)));
+        assert_eq!(data.data, vec!["ab", "cd", "e"]);
+    }
+}
@@ -8,90 +8,57 @@
 //!             AutomatonResult
 //!         },    
 //!         automaton_state::new_shared_concrete_state,
-//!         simple_impl::simple_state::
+//!         simple_impl::
 //!         {
-//!             KeyProvidingData,
-//!             SimpleInterStateConnection,
-//!             SimpleStateImplementation
+//!             key_sources::{char_indices, KeyedData, PeekableKeys},
+//!             matchers::{eq, on, Matcher},
+//!             simple_state::{SimpleInterStateConnection, SimpleStateImplementation}
 //!         }
 //! };
-//! 
-//!  struct TextMatching<'a> {
-//!     text: &'a str,
-//!     matches: Vec<usize>,
-//!     iter: usize,
-//! }
-//! 
-//! impl <'a> TextMatching<'a> {
-//!     pub fn new(
-//!         text: &'a str
-//!     ) -> Self {
-//!         Self { text, matches: Vec::new(), iter: 0}
-//!     }
-//!     pub fn add_match(
-//!         &mut self, index: usize
-//!     ) -> () {
-//!         self.matches.push(index);
-//!     }
-//! }
-//! 
-//! impl <'a> KeyProvidingData<(usize, char)> for TextMatching<'a> {
-//!     fn next_key(
-//!         &mut self
-//!     ) -> Option<(usize, char)> {
-//!         if self.iter >= self.text.len() {
-//!             Option::None
-//!         } else {
-//!             self.iter += 1;
-//!             Option::Some((self.iter - 1, self.text.chars().nth(self.iter)?))
-//!         }
-//!     }
-//! }
-//! 
-//! fn char_matcher(
-//!     c: char,
-//!     reversed: bool
-//! ) -> impl Fn(&(usize, char)) -> bool {
-//!     move |k| (k.1 == c) ^ reversed
+//! use std::str::CharIndices;
+//!
+//! // Keys (positions and characters) are taken from the text, positions of matches are collected in a vector.
+//! type TextMatching<'a> = KeyedData<PeekableKeys<CharIndices<'a>>, Vec<usize>>;
+//!
+//! fn char_matcher<'a>(
+//!     c: char
+//! ) -> Matcher<'a, (usize, char)> {
+//!     on(|k: &(usize, char)| k.1, eq(c))
 //! }
-//! 
-//! let mut matching_data = TextMatching::new("aabbacacaabab");
+//!
+//! let mut matching_data = KeyedData::new(char_indices("aabbacacaabab"), Vec::new());
 //! let mut automaton: Automaton<u32, TextMatching, String> = Automaton::new(|| {
 //!     let non_match_state = new_shared_concrete_state(SimpleStateImplementation::new(0));
-//!     non_match_state.borrow_mut().register_connection(
-//!         SimpleInterStateConnection::new_no_action(char_matcher('a', true), &non_match_state)
-//!     );
-//!
 //!     let a_state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+//!     let b_state = new_shared_concrete_state(SimpleStateImplementation::new(2));
+//!     // Any character other than "a" makes the automaton go back to the initial state.
 //!     non_match_state.borrow_mut().register_connection(
-//!         SimpleInterStateConnection::new_no_action(char_matcher('a', false), &a_state)
-//!     );
-//!     a_state.borrow_mut().register_connection(
-//!         SimpleInterStateConnection::new_no_action(char_matcher('a', false), &a_state)
+//!         SimpleInterStateConnection::new_matcher_no_action(char_matcher('a'), &a_state)
 //!     );
+//!     non_match_state.borrow_mut().set_fallback_connection_no_action(&non_match_state);
+//!
 //!     a_state.borrow_mut().register_connection(
-//!         SimpleInterStateConnection::new_no_action(char_matcher('b', true), &non_match_state)
+//!         SimpleInterStateConnection::new_matcher_no_action(char_matcher('a'), &a_state)
 //!     );
-//!     
-//!     let b_state = new_shared_concrete_state(SimpleStateImplementation::new(2));
 //!     a_state.borrow_mut().register_connection(
-//!         SimpleInterStateConnection::new(char_matcher('b', false),
-//!         |data: &mut TextMatching, key| {
-//!             data.add_match(key.0);
+//!         SimpleInterStateConnection::new_matcher(char_matcher('b'),
+//!         |matching: &mut TextMatching, key| {
+//!             matching.data.push(key.0);
 //!             Result::Ok(())
 //!         }, &b_state)
 //!     );
+//!     a_state.borrow_mut().set_fallback_connection_no_action(&non_match_state);
+//!
 //!     b_state.borrow_mut().register_connection(
-//!         SimpleInterStateConnection::new_no_action(char_matcher('a', false), &a_state)
-//!     );
-//!     b_state.borrow_mut().register_connection(
-//!         SimpleInterStateConnection::new_no_action(char_matcher('a', true), &non_match_state)
+//!         SimpleInterStateConnection::new_matcher_no_action(char_matcher('a'), &a_state)
 //!     );
+//!     b_state.borrow_mut().set_fallback_connection_no_action(&non_match_state);
 //!     non_match_state
 //! });
 //! let result = automaton.run(&mut matching_data);
 //! assert!(result.is_empty_iter());
-//! assert_eq!(matching_data.matches, vec![1, 9, 11]);
+//! // Positions of the "b" character of each match.
+//! assert_eq!(matching_data.data, vec![2, 10, 12]);
 //! ```
 
 
@@ -99,3 +66,31 @@
 /// states and allows for some action to be executed while changing states. Designed to be used
 /// in parser like solutions.
 pub mod simple_state;
+/// Composable key matchers carrying descriptions of accepted keys.
+pub mod matchers;
+/// Sets of keys described by sorted, merged ranges, used by connections matching character classes and numeric ranges.
+pub mod range_set;
+/// States finding connections for exact keys through a map, designed for states with many connections.
+pub mod indexed_state;
+/// Implementations of key providing data for standard types.
+pub mod key_sources;
+/// Transducers (Mealy machines) emitting output symbols while changing states, and pipelines made of them.
+pub mod transducer;
+/// States of Moore machines, which emit their outputs whenever they become active.
+pub mod moore;
+/// Nondeterministic automata tracking sets of active states, with connections leading to several states and epsilon
+/// connections.
+pub mod nfa;
+/// Deterministic automata compiled from nondeterministic ones with subset construction.
+pub mod dfa;
+/// Tracking of source positions (offsets, lines and columns) of provided keys.
+pub mod positions;
+/// Serialisable description of a graph made of simple states. Allows for storing automata configurations and building
+/// equivalent automata from them.
+pub mod definition;
+/// Creation of graphs made of simple states where connections refer to states by identifiers.
+pub mod builder;
+/// State running another automaton, allowing for composing automata.
+pub mod sub_automaton;
+/// Declarative macro for defining graphs made of simple states.
+mod macros;
@@ -0,0 +1,127 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::{automaton::NextState, automaton_state::AutomatonState, key_provider::KeyProvidingData, simple_impl::{simple_state::SimpleStateImplementation, transducer::{OutputSink, Transduction}}};
+
+/// State with an output value (state of a Moore machine).
+pub trait MooreOutput<O> {
+    /// Returns output of this state.
+    fn output(&self) -> &O;
+}
+
+/// Operation notified whenever a state becomes active. Receives identifier and output of the state.
+type EntryObserver<'a, Id, O> = Box<dyn Fn(&Id, &O) + 'a>;
+
+/// State of a Moore machine - a [`SimpleStateImplementation`] with an output value. Whenever the state becomes active
+/// (including the start of a run), its output is emitted into the output sink of the [`Transduction`] and registered
+/// observers are notified (after the entry action of the state). Running an automaton made of these states with
+/// `Automaton::transduce` returns the sequence of outputs of visited states.
+///
+/// Connections and actions are managed through the wrapped state (available through dereferencing).
+pub struct MooreState<'a, K, Id, T, O, Out, E> where T: KeyProvidingData<K>, Id: Copy {
+    state: SimpleStateImplementation<'a, K, Id, Transduction<T, Out>, E>,
+    output: O,
+    observers: Vec<EntryObserver<'a, Id, O>>,
+}
+
+impl <'a, K, Id, T, O, Out, E> MooreState<'a, K, Id, T, O, Out, E> where T: KeyProvidingData<K>, Id: Copy {
+    /// Creates new state with provided identifier and output.
+    pub fn new(id: Id, output: O) -> Self {
+        Self { state: SimpleStateImplementation::new(id), output, observers: Vec::new() }
+    }
+
+    /// Replaces output of this state.
+    pub fn set_output(&mut self, output: O) {
+        self.output = output;
+    }
+
+    /// Adds operation notified whenever this state becomes active.
+    pub fn register_observer<FObserver: Fn(&Id, &O) + 'a>(&mut self, observer: FObserver) {
+        self.observers.push(Box::new(observer));
+    }
+}
+
+impl <'a, K, Id, T, O, Out, E> MooreOutput<O> for MooreState<'a, K, Id, T, O, Out, E> where T: KeyProvidingData<K>, Id: Copy {
+    fn output(&self) -> &O {
+        &self.output
+    }
+}
+
+impl <'a, K, Id, T, O, Out, E> Deref for MooreState<'a, K, Id, T, O, Out, E> where T: KeyProvidingData<K>, Id: Copy {
+    type Target = SimpleStateImplementation<'a, K, Id, Transduction<T, Out>, E>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.state
+    }
+}
+
+impl <'a, K, Id, T, O, Out, E> DerefMut for MooreState<'a, K, Id, T, O, Out, E> where T: KeyProvidingData<K>, Id: Copy {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.state
+    }
+}
+
+impl <'a, K, Id, T, O, Out, E> AutomatonState<'a, Id, Transduction<T, Out>, E> for MooreState<'a, K, Id, T, O, Out, E>
+where T: KeyProvidingData<K>, Id: Copy, O: Clone, Out: OutputSink<O> {
+    fn get_id_owned(&self) -> Id {
+        self.state.get_id_owned()
+    }
+
+    fn get_id(&self) -> &Id {
+        self.state.get_id()
+    }
+
+    fn execute_next_connection(&self, data: &mut Transduction<T, Out>) -> Result<NextState<'a, Id, Transduction<T, Out>, E>, E> {
+        self.state.execute_next_connection(data)
+    }
+
+    /// Executes entry action of the wrapped state, emits output and notifies observers.
+    fn execute_entry(&self, data: &mut Transduction<T, Out>) -> Result<(), E> {
+        self.state.execute_entry(data)?;
+        data.output.emit(self.output.clone());
+        for observer in &self.observers {
+            observer(self.state.get_id(), &self.output);
+        }
+        Result::Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+    use crate::{automaton::{Automaton, AutomatonResult}, automaton_state::new_shared_concrete_state, simple_impl::{simple_state::SimpleInterStateConnection, transducer::Transduction}};
+
+    use super::{MooreOutput, MooreState};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Light {
+        Red,
+        Green,
+        Yellow,
+    }
+
+    #[test]
+    fn moore_states_emit_outputs_of_visited_states() {
+        let entries = Rc::new(RefCell::new(Vec::new()));
+        let observed = Rc::clone(&entries);
+        let mut automaton: Automaton<u8, Transduction<VecDeque<char>, Vec<Light>>, String> = Automaton::new(move || {
+            let red = new_shared_concrete_state(MooreState::new(1, Light::Red));
+            let green = new_shared_concrete_state(MooreState::new(2, Light::Green));
+            let yellow = new_shared_concrete_state(MooreState::new(3, Light::Yellow));
+            red.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k| *k == 't', &green));
+            green.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k| *k == 't', &yellow));
+            yellow.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k| *k == 't', &red));
+            for state in [&red, &green, &yellow] {
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k| *k == 'w', state));
+            }
+            let observed = Rc::clone(&observed);
+            yellow.borrow_mut().register_observer(move |id, output| observed.borrow_mut().push((*id, *output)));
+            assert_eq!(*green.borrow().output(), Light::Green);
+            red
+        });
+        let (result, outputs) = automaton.transduce(VecDeque::from(vec!['t', 'w', 't', 'w', 't']));
+        assert!(matches!(result, AutomatonResult::EmptyIter(1)));
+        assert_eq!(outputs, vec![Light::Red, Light::Green, Light::Green, Light::Yellow, Light::Yellow, Light::Red]);
+        assert_eq!(*entries.borrow(), vec![(3, Light::Yellow), (3, Light::Yellow)]);
+    }
+}
@@ -0,0 +1,345 @@
+use std::{collections::HashMap, hash::Hash};
+
+use crate::{key_provider::KeyProvidingData, simple_impl::{definition::DefinitionError, range_set::RangeSet, simple_state::KeyMatcher}};
+
+/// Decides which keys are accepted by a connection of a nondeterministic automaton. Exact keys and ranges can be
+/// inspected (e.g. for converting the automaton into a deterministic one), predicates can't.
+pub enum NfaMatcher<'a, K> {
+    Key(K),
+    Ranges(RangeSet<K>),
+    Predicate(KeyMatcher<'a, K>),
+}
+
+impl <K: Ord> NfaMatcher<'_, K> {
+    /// Checks whether the key is accepted.
+    pub fn matches(&self, key: &K) -> bool {
+        match self {
+            NfaMatcher::Key(expected) => expected == key,
+            NfaMatcher::Ranges(ranges) => ranges.contains(key),
+            NfaMatcher::Predicate(predicate) => predicate(key),
+        }
+    }
+}
+
+/// Connection of a nondeterministic automaton. Matched connection activates all of its targets.
+pub struct NfaConnection<'a, K> {
+    matcher: NfaMatcher<'a, K>,
+    targets: Vec<usize>,
+}
+
+impl <'a, K> NfaConnection<'a, K> {
+    /// Returns matcher of this connection.
+    pub fn matcher(&self) -> &NfaMatcher<'a, K> {
+        &self.matcher
+    }
+
+    /// Returns indices (positions in [`Nfa::states`]) of states activated by this connection.
+    pub fn targets(&self) -> &[usize] {
+        &self.targets
+    }
+}
+
+/// State of a nondeterministic automaton. Any number of connections can match the same key and each of them can lead
+/// to several states. Epsilon connections activate their targets without consuming keys.
+pub struct NfaState<'a, K, Id> {
+    id: Id,
+    accepting: bool,
+    connections: Vec<NfaConnection<'a, K>>,
+    epsilon_targets: Vec<usize>,
+}
+
+impl <'a, K, Id> NfaState<'a, K, Id> {
+    /// Returns identifier of this state.
+    pub fn id(&self) -> &Id {
+        &self.id
+    }
+
+    /// Checks whether the automaton accepts the input if it ends while this state is active.
+    pub fn is_accepting(&self) -> bool {
+        self.accepting
+    }
+
+    /// Returns connections consuming keys, in the order of declaration.
+    pub fn connections(&self) -> &[NfaConnection<'a, K>] {
+        &self.connections
+    }
+
+    /// Returns indices (positions in [`Nfa::states`]) of states activated without consuming keys.
+    pub fn epsilon_targets(&self) -> &[usize] {
+        &self.epsilon_targets
+    }
+}
+
+/// Connection waiting for its states to be declared.
+struct PendingNfaConnection<'a, K, Id> {
+    source: Id,
+    matcher: Option<NfaMatcher<'a, K>>,
+    targets: Vec<Id>,
+}
+
+/// Creates [`Nfa`] where connections refer to states by identifiers. Like [`AutomatonBuilder`](crate::simple_impl::builder::AutomatonBuilder),
+/// references are resolved when [`NfaBuilder::build`] is called, so the order of declarations doesn't matter.
+pub struct NfaBuilder<'a, K, Id> {
+    start_state: Id,
+    states: Vec<(Id, bool)>,
+    connections: Vec<PendingNfaConnection<'a, K, Id>>,
+}
+
+impl <'a, K, Id> NfaBuilder<'a, K, Id> where Id: Copy + Eq + Hash {
+    /// Creates builder for an automaton starting on the state with provided identifier. Unlike [`AutomatonBuilder::new`](crate::simple_impl::builder::AutomatonBuilder::new),
+    /// the start state still has to be declared, as either [`NfaBuilder::add_state`] or [`NfaBuilder::add_accepting_state`]
+    /// decides whether it accepts the input.
+    pub fn new(start_state: Id) -> Self {
+        Self { start_state, states: Vec::new(), connections: Vec::new() }
+    }
+
+    /// Declares state that doesn't accept the input.
+    pub fn add_state(&mut self, id: Id) {
+        self.states.push((id, false));
+    }
+
+    /// Declares accepting state.
+    pub fn add_accepting_state(&mut self, id: Id) {
+        self.states.push((id, true));
+    }
+
+    /// Declares connection activating all targets when the key is equal to provided one.
+    pub fn add_connection<I: IntoIterator<Item = Id>>(&mut self, source: Id, key: K, targets: I) {
+        self.add_matcher_connection(source, NfaMatcher::Key(key), targets);
+    }
+
+    /// Declares connection activating all targets when the key belongs to provided set.
+    pub fn add_range_connection<I: IntoIterator<Item = Id>>(&mut self, source: Id, ranges: RangeSet<K>, targets: I) {
+        self.add_matcher_connection(source, NfaMatcher::Ranges(ranges), targets);
+    }
+
+    /// Declares connection activating all targets when the key is accepted by provided predicate.
+    pub fn add_predicate_connection<M: Fn(&K) -> bool + 'a, I: IntoIterator<Item = Id>>(&mut self, source: Id, matcher: M, targets: I) {
+        self.add_matcher_connection(source, NfaMatcher::Predicate(Box::new(matcher)), targets);
+    }
+
+    /// Declares connection with provided matcher.
+    pub fn add_matcher_connection<I: IntoIterator<Item = Id>>(&mut self, source: Id, matcher: NfaMatcher<'a, K>, targets: I) {
+        self.connections.push(PendingNfaConnection { source, matcher: Option::Some(matcher), targets: targets.into_iter().collect() });
+    }
+
+    /// Declares epsilon connection - target is active whenever the source is active.
+    pub fn add_epsilon_connection(&mut self, source: Id, target: Id) {
+        self.connections.push(PendingNfaConnection { source, matcher: Option::None, targets: vec![target] });
+    }
+
+    /// Resolves connections and returns the automaton. Fails if an identifier was declared more than once or if the
+    /// start state or any connection refers to an undeclared state.
+    pub fn build(self) -> Result<Nfa<'a, K, Id>, DefinitionError<Id>> {
+        let mut indices = HashMap::with_capacity(self.states.len());
+        let mut states = Vec::with_capacity(self.states.len());
+        for (id, accepting) in self.states {
+            if indices.insert(id, states.len()).is_some() {
+                return Result::Err(DefinitionError::DuplicateState(id));
+            }
+            states.push(NfaState { id, accepting, connections: Vec::new(), epsilon_targets: Vec::new() });
+        }
+        let start_state = *indices.get(&self.start_state).ok_or(DefinitionError::UnknownStartState(self.start_state))?;
+        for connection in self.connections {
+            let source = *indices.get(&connection.source).ok_or(DefinitionError::UnknownConnectionSource(connection.source))?;
+            let targets = connection.targets.into_iter()
+                .map(|target| indices.get(&target).copied().ok_or(DefinitionError::UnknownConnectionTarget(target)))
+                .collect::<Result<Vec<usize>, DefinitionError<Id>>>()?;
+            match connection.matcher {
+                Option::Some(matcher) => states[source].connections.push(NfaConnection { matcher, targets }),
+                Option::None => states[source].epsilon_targets.extend(targets),
+            }
+        }
+        Result::Ok(Nfa { start_state, states })
+    }
+}
+
+/// Nondeterministic finite automaton. Instead of following a single state, a run tracks the set of all active states:
+/// each key activates targets of all matching connections of all active states, along with states reachable from them
+/// through epsilon connections (cycles of epsilon connections are allowed). The input is accepted if any state active
+/// after the last key is accepting.
+///
+/// Connections don't execute operations on data, since a key can be consumed by several connections at once. Keys are
+/// taken from the data one by one, so the key source is never cloned or rewound.
+pub struct Nfa<'a, K, Id> {
+    start_state: usize,
+    states: Vec<NfaState<'a, K, Id>>,
+}
+
+impl <'a, K, Id> Nfa<'a, K, Id> {
+    /// Returns all states in the order of declaration. Connections refer to states by their positions in this slice.
+    pub fn states(&self) -> &[NfaState<'a, K, Id>] {
+        &self.states
+    }
+
+    /// Returns index (position in [`Nfa::states`]) of the start state.
+    pub fn start_state(&self) -> usize {
+        self.start_state
+    }
+
+    /// Returns sorted indices of provided states and all states reachable from them through epsilon connections.
+    pub fn epsilon_closure<I: IntoIterator<Item = usize>>(&self, states: I) -> Vec<usize> {
+        let mut reached = vec![false; self.states.len()];
+        let mut pending: Vec<usize> = states.into_iter().collect();
+        while let Option::Some(state) = pending.pop() {
+            if !reached[state] {
+                reached[state] = true;
+                pending.extend(self.states[state].epsilon_targets.iter().filter(|target| !reached[**target]));
+            }
+        }
+        reached.into_iter().enumerate().filter_map(|(index, is_reached)| is_reached.then_some(index)).collect()
+    }
+
+    /// Creates run starting on the epsilon closure of the start state.
+    pub fn start(&self) -> NfaRun<'_, 'a, K, Id> {
+        NfaRun { nfa: self, active: self.epsilon_closure([self.start_state]), consumed_keys: 0 }
+    }
+}
+
+impl <K: Ord, Id: Copy> Nfa<'_, K, Id> {
+    /// Consumes keys until they end or until no state is active.
+    pub fn run<D: KeyProvidingData<K>>(&self, data: &mut D) -> NfaResult<Id> {
+        self.start().run(data)
+    }
+}
+
+/// Set of active states of a nondeterministic automaton, advanced with each consumed key.
+pub struct NfaRun<'n, 'a, K, Id> {
+    nfa: &'n Nfa<'a, K, Id>,
+    active: Vec<usize>,
+    consumed_keys: usize,
+}
+
+impl <K: Ord, Id: Copy> NfaRun<'_, '_, K, Id> {
+    /// Replaces active states with targets of connections matching the key (and their epsilon closure). Returns `false`
+    /// if no state is active afterwards.
+    pub fn step(&mut self, key: &K) -> bool {
+        let states = &self.nfa.states;
+        let targets = self.active.iter()
+            .flat_map(|state| states[*state].connections.iter())
+            .filter(|connection| connection.matcher.matches(key))
+            .flat_map(|connection| connection.targets.iter().copied());
+        self.active = self.nfa.epsilon_closure(targets);
+        self.consumed_keys += 1;
+        !self.active.is_empty()
+    }
+
+    /// Consumes keys from the data until they end or until no state is active. Keys following the one no state matched
+    /// are left in the data.
+    pub fn run<D: KeyProvidingData<K>>(&mut self, data: &mut D) -> NfaResult<Id> {
+        while !self.active.is_empty() {
+            match data.next_key() {
+                Option::Some(key) => self.step(&key),
+                Option::None => break,
+            };
+        }
+        NfaResult { accepted: self.is_accepting(), active_states: self.active_states(), consumed_keys: self.consumed_keys }
+    }
+
+    /// Checks whether any active state is accepting.
+    pub fn is_accepting(&self) -> bool {
+        self.active.iter().any(|state| self.nfa.states[*state].accepting)
+    }
+
+    /// Returns identifiers of active states in the order of declaration.
+    pub fn active_states(&self) -> Vec<Id> {
+        self.active.iter().map(|state| self.nfa.states[*state].id).collect()
+    }
+
+    /// Returns indices (positions in [`Nfa::states`]) of active states, sorted.
+    pub fn active_indices(&self) -> &[usize] {
+        &self.active
+    }
+
+    /// Returns number of keys consumed so far.
+    pub fn consumed_keys(&self) -> usize {
+        self.consumed_keys
+    }
+}
+
+/// Outcome of a nondeterministic automaton run. If no state matched a key, `active_states` is empty and the input is
+/// not accepted.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NfaResult<Id> {
+    /// Whether any of the states active when the run stopped is accepting.
+    pub accepted: bool,
+    /// Identifiers of states active when the run stopped, in the order of declaration.
+    pub active_states: Vec<Id>,
+    /// Number of keys taken from the data (including the key no state matched).
+    pub consumed_keys: usize,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{key_provider::KeyProvidingData, simple_impl::{definition::DefinitionError, key_sources::{chars, read_chars}, range_set::RangeSet}};
+
+    use super::{Nfa, NfaBuilder, NfaResult};
+
+    /// Accepts texts made of 'a' and 'b' ending with "ab".
+    fn ends_with_ab<'a>() -> Nfa<'a, char, u8> {
+        let mut builder = NfaBuilder::new(0);
+        builder.add_state(0);
+        builder.add_state(1);
+        builder.add_accepting_state(2);
+        builder.add_connection(0, 'a', [0, 1]);
+        builder.add_connection(0, 'b', [0]);
+        builder.add_connection(1, 'b', [2]);
+        builder.build().unwrap()
+    }
+
+    /// Accepts integers with optional sign. Epsilon connections form a cycle.
+    fn integer<'a>() -> Nfa<'a, char, &'static str> {
+        let mut builder = NfaBuilder::new("start");
+        builder.add_accepting_state("digits");
+        builder.add_state("start");
+        builder.add_state("sign");
+        builder.add_connection("start", '-', ["sign"]);
+        builder.add_range_connection("start", RangeSet::from_ranges(['+'..='+']), ["sign"]);
+        builder.add_epsilon_connection("start", "sign");
+        builder.add_epsilon_connection("sign", "start");
+        builder.add_range_connection("sign", RangeSet::from_ranges(['0'..='9']), ["digits"]);
+        builder.add_predicate_connection("digits", |k: &char| k.is_ascii_digit(), ["digits"]);
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn nfa_tracks_all_active_states() {
+        let nfa = ends_with_ab();
+        assert_eq!(nfa.run(&mut chars("abbab")), NfaResult { accepted: true, active_states: vec![0, 2], consumed_keys: 5 });
+        assert_eq!(nfa.run(&mut read_chars("abba".as_bytes())), NfaResult { accepted: false, active_states: vec![0, 1], consumed_keys: 4 });
+        let mut run = nfa.start();
+        let accepting: Vec<bool> = "aabab".chars().map(|key| {
+            run.step(&key);
+            run.is_accepting()
+        }).collect();
+        assert_eq!(accepting, vec![false, false, true, false, true]);
+        assert_eq!(run.active_indices(), &[0, 2]);
+    }
+
+    #[test]
+    fn nfa_follows_epsilon_connections() {
+        let nfa = integer();
+        assert_eq!(nfa.start().active_states(), vec!["start", "sign"]);
+        assert!(nfa.run(&mut chars("-120")).accepted);
+        assert!(nfa.run(&mut chars("7")).accepted);
+        assert!(!nfa.run(&mut chars("+")).accepted);
+        assert!(!nfa.run(&mut chars("")).accepted);
+        let mut keys = chars("12-3");
+        assert_eq!(nfa.run(&mut keys), NfaResult { accepted: false, active_states: Vec::new(), consumed_keys: 3 });
+        assert_eq!(keys.next_key(), Option::Some('3'));
+    }
+
+    #[test]
+    fn nfa_builder_rejects_undeclared_states() {
+        let mut builder: NfaBuilder<char, u8> = NfaBuilder::new(0);
+        builder.add_state(0);
+        builder.add_connection(0, 'a', [0, 3]);
+        assert_eq!(builder.build().err().unwrap(), DefinitionError::UnknownConnectionTarget(3));
+
+        let mut builder: NfaBuilder<char, u8> = NfaBuilder::new(0);
+        builder.add_state(0);
+        builder.add_epsilon_connection(1, 0);
+        assert_eq!(builder.build().err().unwrap(), DefinitionError::UnknownConnectionSource(1));
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::VecDeque;
+
+use crate::{automaton::{Automaton, AutomatonResult}, simple_impl::{key_sources::KeyedData, simple_state::{KeyProvidingData, PeekingKeyProvidingData, PushbackKeyProvidingData}}};
+
+/// Number of recently consumed keys whose positions are remembered for restoring positions of pushed back keys.
+const DEFAULT_HISTORY_LENGTH: usize = 64;
+
+/// Location of a key in the source. Offsets start at `0`, lines and columns start at `1`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourcePosition {
+    pub byte_offset: usize,
+    pub char_offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for SourcePosition {
+    fn default() -> Self {
+        Self { byte_offset: 0, char_offset: 0, line: 1, column: 1 }
+    }
+}
+
+impl SourcePosition {
+    /// Returns position following given key.
+    fn advance<K: PositionedKey>(&self, key: &K) -> Self {
+        let mut next = *self;
+        next.byte_offset += key.byte_length();
+        if key.starts_char() {
+            next.char_offset += 1;
+            if key.is_line_break() {
+                next.line += 1;
+                next.column = 1;
+            } else {
+                next.column += 1;
+            }
+        }
+        next
+    }
+}
+
+/// Key that takes a known part of the source.
+pub trait PositionedKey {
+    /// Number of bytes taken by the key.
+    fn byte_length(&self) -> usize;
+    /// Whether the key starts a new character (`false` for continuation bytes of multi-byte characters).
+    fn starts_char(&self) -> bool;
+    /// Whether the key ends a line.
+    fn is_line_break(&self) -> bool;
+}
+
+impl PositionedKey for char {
+    fn byte_length(&self) -> usize {
+        self.len_utf8()
+    }
+
+    fn starts_char(&self) -> bool {
+        true
+    }
+
+    fn is_line_break(&self) -> bool {
+        *self == '\n'
+    }
+}
+
+/// Bytes are treated as UTF-8 encoded text.
+impl PositionedKey for u8 {
+    fn byte_length(&self) -> usize {
+        1
+    }
+
+    fn starts_char(&self) -> bool {
+        (*self & 0b1100_0000) != 0b1000_0000
+    }
+
+    fn is_line_break(&self) -> bool {
+        *self == b'\n'
+    }
+}
+
+/// Characters along with their byte offsets (as provided by `str::char_indices`).
+impl PositionedKey for (usize, char) {
+    fn byte_length(&self) -> usize {
+        self.1.byte_length()
+    }
+
+    fn starts_char(&self) -> bool {
+        true
+    }
+
+    fn is_line_break(&self) -> bool {
+        self.1.is_line_break()
+    }
+}
+
+/// Data that knows positions of the keys it provides.
+pub trait PositionedData {
+    /// Returns position of the most recently consumed key (that wasn't pushed back), which is the key currently processed
+    /// by the automaton. If the automaton stopped on a key that was restored to the data, returns position of that key.
+    /// Returns position of the first key if nothing was consumed.
+    fn key_position(&self) -> SourcePosition;
+    /// Returns position of the next key.
+    fn position(&self) -> SourcePosition;
+}
+
+/// Key source wrapper recording positions of provided keys. Positions of keys pushed back to the source are restored
+/// from the positions of recently consumed keys, so history has to be at least as long as the number of keys pushed
+/// back at once (e.g. length of the longest sequence or slice connection). Pushing back more keys than remembered
+/// panics (also in release builds), because positions of all following keys would be wrong.
+pub struct PositionedKeys<S> {
+    keys: S,
+    next: SourcePosition,
+    history: VecDeque<SourcePosition>,
+    history_length: usize,
+    /// Whether the last key was restored after no connection matched it (it's the next key then).
+    unmatched_restored: bool,
+}
+
+impl <S> PositionedKeys<S> {
+    /// Creates wrapper starting at the beginning of the source.
+    pub fn new(keys: S) -> Self {
+        Self::with_history(keys, DEFAULT_HISTORY_LENGTH)
+    }
+
+    /// Creates wrapper remembering positions of up to `history_length` consumed keys. History of length `0` can be
+    /// used only with automata that never push keys back (including unmatched keys).
+    pub fn with_history(keys: S, history_length: usize) -> Self {
+        Self { keys, next: SourcePosition::default(), history: VecDeque::new(), history_length, unmatched_restored: false }
+    }
+
+    /// Returns wrapped key source.
+    pub fn get_ref(&self) -> &S {
+        &self.keys
+    }
+
+    /// Moves position back to the most recently consumed key.
+    fn restore_position(&mut self) {
+        match self.history.pop_back() {
+            Option::Some(position) => self.next = position,
+            Option::None => panic!("key pushed back to PositionedKeys without remembered position (history length: {})", self.history_length),
+        }
+    }
+}
+
+impl <S> PositionedData for PositionedKeys<S> {
+    fn key_position(&self) -> SourcePosition {
+        if self.unmatched_restored {
+            return self.next;
+        }
+        self.history.back().copied().unwrap_or(self.next)
+    }
+
+    fn position(&self) -> SourcePosition {
+        self.next
+    }
+}
+
+impl <K: PositionedKey, S: KeyProvidingData<K>> KeyProvidingData<K> for PositionedKeys<S> {
+    fn next_key(&mut self) -> Option<K> {
+        let key = self.keys.next_key()?;
+        self.unmatched_restored = false;
+        if self.history.len() == self.history_length {
+            self.history.pop_front();
+        }
+        if self.history_length > 0 {
+            self.history.push_back(self.next);
+        }
+        self.next = self.next.advance(&key);
+        Option::Some(key)
+    }
+
+    fn restore_unmatched_key(&mut self, key: K) -> bool {
+        let restored = self.keys.restore_unmatched_key(key);
+        if restored {
+            self.restore_position();
+            self.unmatched_restored = true;
+        }
+        restored
+    }
+}
+
+impl <K: PositionedKey, S: PeekingKeyProvidingData<K>> PeekingKeyProvidingData<K> for PositionedKeys<S> {
+    fn peek_key(&mut self, offset: usize) -> Option<K> {
+        self.keys.peek_key(offset)
+    }
+}
+
+impl <K: PositionedKey, S: PushbackKeyProvidingData<K>> PushbackKeyProvidingData<K> for PositionedKeys<S> {
+    fn push_back_key(&mut self, key: K) {
+        self.unmatched_restored = false;
+        self.restore_position();
+        self.keys.push_back_key(key);
+    }
+}
+
+impl <S: PositionedData, T> PositionedData for KeyedData<S, T> {
+    fn key_position(&self) -> SourcePosition {
+        self.keys.key_position()
+    }
+
+    fn position(&self) -> SourcePosition {
+        self.keys.position()
+    }
+}
+
+impl <'a, Id, D, E> Automaton<'a, Id, D, E> where D: PositionedData {
+    /// Starts automaton with given data. Results of unmatched keys and errors contain position of the key that was
+    /// processed when the automaton stopped (also when the unmatched key was restored to the data).
+    pub fn run_positioned(&mut self, data: &mut D) -> AutomatonResult<Id, E, SourcePosition> {
+        let result = self.run(data);
+        result.with_position(data.key_position())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{automaton::{Automaton, AutomatonResult}, automaton_state::new_shared_concrete_state, simple_impl::{key_sources::{chars, read_bytes, PeekableKeys}, simple_state::{KeyProvidingData, PushbackKeyProvidingData, SimpleInterStateConnection, SimpleStateImplementation}}};
+
+    use super::{PositionedData, PositionedKeys, SourcePosition};
+
+    fn at(byte_offset: usize, char_offset: usize, line: usize, column: usize) -> SourcePosition {
+        SourcePosition { byte_offset, char_offset, line, column }
+    }
+
+    #[test]
+    fn positions_follow_lines_and_multi_byte_characters() {
+        let mut keys = PositionedKeys::new(chars("ąb\nc"));
+        assert_eq!(keys.key_position(), at(0, 0, 1, 1));
+        keys.next_key();
+        keys.next_key();
+        assert_eq!(keys.key_position(), at(2, 1, 1, 2));
+        keys.next_key();
+        keys.next_key();
+        assert_eq!(keys.key_position(), at(4, 3, 2, 1));
+        assert_eq!(keys.position(), at(5, 4, 2, 2));
+
+        let mut bytes = PositionedKeys::new(read_bytes("ą\nb".as_bytes()));
+        while bytes.next_key().is_some() {}
+        assert_eq!(bytes.key_position(), at(3, 2, 2, 1));
+    }
+
+    #[test]
+    fn pushed_back_keys_restore_positions() {
+        let mut keys = PositionedKeys::new(chars("a\nbc"));
+        keys.next_key();
+        keys.next_key();
+        let b = keys.next_key().unwrap();
+        keys.push_back_key(b);
+        assert_eq!(keys.key_position(), at(1, 1, 1, 2));
+        assert_eq!(keys.position(), at(2, 2, 2, 1));
+        assert_eq!(keys.next_key(), Option::Some('b'));
+        assert_eq!(keys.key_position(), at(2, 2, 2, 1));
+    }
+
+    type Data<'t> = PositionedKeys<PeekableKeys<std::str::Chars<'t>>>;
+
+    fn digits_automaton<'a, 't: 'a>() -> Automaton<'a, u8, Data<'t>, String> {
+        Automaton::new(|| {
+            let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+            state.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k: &char| k.is_ascii_digit() || *k == '\n', &state));
+            state.borrow_mut().register_connection(SimpleInterStateConnection::new(|k| *k == '!', |d: &mut Data, _| {
+                let position = d.key_position();
+                Result::Err(format!("unexpected '!' at {}:{}", position.line, position.column))
+            }, &state));
+            state
+        })
+    }
+
+    #[test]
+    fn results_contain_positions() {
+        let mut data = PositionedKeys::new(chars("12\n3x4"));
+        let result = digits_automaton().run_positioned(&mut data);
+        assert!(matches!(result, AutomatonResult::CouldNotFindNextState(1, position) if position == at(4, 4, 2, 2)));
+        assert_eq!(data.next_key(), Option::Some('x'));
+
+        let mut data = PositionedKeys::new(chars("1\n\n23!"));
+        let result = digits_automaton().run_positioned(&mut data);
+        assert!(matches!(result, AutomatonResult::Error(ref err, position) if err == "unexpected '!' at 3:3" && position == at(5, 5, 3, 3)));
+    }
+
+    #[test]
+    #[should_panic(expected = "without remembered position")]
+    fn pushing_back_more_keys_than_remembered_panics() {
+        let mut keys = PositionedKeys::with_history(chars("abc"), 1);
+        let a = keys.next_key().unwrap();
+        let b = keys.next_key().unwrap();
+        keys.push_back_key(b);
+        keys.push_back_key(a);
+    }
+
+    #[test]
+    #[should_panic(expected = "without remembered position")]
+    fn sequence_longer_than_history_panics() {
+        let mut data = PositionedKeys::with_history(chars("abcx"), 2);
+        let mut automaton: Automaton<u8, Data, String> = Automaton::new(|| {
+            let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+            state.borrow_mut().register_connection(SimpleInterStateConnection::new_sequence_no_action(vec!['a', 'b', 'c', 'd'], &state));
+            state
+        });
+        automaton.run(&mut data);
+    }
+}
@@ -0,0 +1,209 @@
+use std::ops::RangeInclusive;
+
+/// Ordered key type for which the neighbouring values are known, so that ranges ending right before another range starts can
+/// be merged and overlapping ranges can be split into disjoint ones.
+pub trait RangeKey: Ord + Copy {
+    /// Returns the smallest value greater than this one. Returns `None` for the greatest value.
+    fn successor(&self) -> Option<Self>;
+    /// Returns the greatest value smaller than this one. Returns `None` for the smallest value.
+    fn predecessor(&self) -> Option<Self>;
+}
+
+macro_rules! impl_range_key_for_integers {
+    ($($t:ty),*) => {
+        $(
+            impl RangeKey for $t {
+                fn successor(&self) -> Option<Self> {
+                    self.checked_add(1)
+                }
+
+                fn predecessor(&self) -> Option<Self> {
+                    self.checked_sub(1)
+                }
+            }
+        )*
+    };
+}
+
+impl_range_key_for_integers!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+impl RangeKey for char {
+    fn successor(&self) -> Option<Self> {
+        match self {
+            '\u{D7FF}' => Option::Some('\u{E000}'),
+            _ => char::from_u32(*self as u32 + 1),
+        }
+    }
+
+    fn predecessor(&self) -> Option<Self> {
+        match self {
+            '\u{E000}' => Option::Some('\u{D7FF}'),
+            _ => (*self as u32).checked_sub(1).and_then(char::from_u32),
+        }
+    }
+}
+
+/// Set of keys described by inclusive ranges. Ranges are stored sorted and merged (no two ranges overlap or are adjacent),
+/// so checking whether a key belongs to the set is a binary search. Unlike closures, the ranges can be inspected (e.g.
+/// for exporting or validating a graph).
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "Vec<(K, K)>", into = "Vec<(K, K)>", bound = "K: RangeKey + serde::Serialize + serde::de::DeserializeOwned"))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RangeSet<K> {
+    ranges: Vec<(K, K)>,
+}
+
+impl <K> Default for RangeSet<K> {
+    fn default() -> Self {
+        Self { ranges: Vec::new() }
+    }
+}
+
+impl <K: RangeKey> RangeSet<K> {
+    /// Creates empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates set containing keys from all provided ranges.
+    pub fn from_ranges<I: IntoIterator<Item = RangeInclusive<K>>>(ranges: I) -> Self {
+        let mut set = Self::new();
+        for range in ranges {
+            set.insert(range);
+        }
+        set
+    }
+
+    /// Adds all keys from the range to the set. Empty ranges are ignored.
+    pub fn insert(&mut self, range: RangeInclusive<K>) {
+        let (start, end) = range.into_inner();
+        if start > end {
+            return;
+        }
+        // Ranges ending before `start` without touching it stay untouched, as do ranges starting after `end`.
+        let first = self.ranges.partition_point(|(_, e)| e.successor().is_some_and(|next| next < start));
+        let last = self.ranges.partition_point(|(s, _)| *s <= end || end.successor() == Option::Some(*s));
+        let merged = if first < last {
+            (start.min(self.ranges[first].0), end.max(self.ranges[last - 1].1))
+        } else {
+            (start, end)
+        };
+        self.ranges.splice(first..last, [merged]);
+    }
+
+    /// Adds single key to the set.
+    pub fn insert_key(&mut self, key: K) {
+        self.insert(key..=key);
+    }
+}
+
+impl <K: Ord> RangeSet<K> {
+    /// Checks whether the key belongs to the set.
+    pub fn contains(&self, key: &K) -> bool {
+        let following = self.ranges.partition_point(|(s, _)| s <= key);
+        following > 0 && self.ranges[following - 1].1 >= *key
+    }
+
+    /// Returns sorted, merged ranges (as pairs of inclusive bounds) making up the set.
+    pub fn ranges(&self) -> &[(K, K)] {
+        &self.ranges
+    }
+
+    /// Checks whether the set contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+impl <K: RangeKey> FromIterator<RangeInclusive<K>> for RangeSet<K> {
+    fn from_iter<T: IntoIterator<Item = RangeInclusive<K>>>(iter: T) -> Self {
+        Self::from_ranges(iter)
+    }
+}
+
+impl <K: RangeKey> From<Vec<(K, K)>> for RangeSet<K> {
+    fn from(ranges: Vec<(K, K)>) -> Self {
+        Self::from_ranges(ranges.into_iter().map(|(start, end)| start..=end))
+    }
+}
+
+impl <K> From<RangeSet<K>> for Vec<(K, K)> {
+    fn from(set: RangeSet<K>) -> Self {
+        set.ranges
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::VecDeque, ops::RangeInclusive};
+
+    use crate::{automaton::{Automaton, AutomatonResult}, automaton_state::new_shared_concrete_state, simple_impl::{key_sources::KeyedData, simple_state::{SimpleInterStateConnection, SimpleStateImplementation}}};
+
+    use super::{RangeKey, RangeSet};
+
+    #[test]
+    fn range_set_merges_overlapping_and_adjacent_ranges() {
+        let mut set = RangeSet::from_ranges([20..=30, 0..=5, 10..=12]);
+        assert_eq!(set.ranges(), &[(0, 5), (10, 12), (20, 30)]);
+        set.insert(6..=9);
+        assert_eq!(set.ranges(), &[(0, 12), (20, 30)]);
+        set.insert(15..=25);
+        set.insert_key(14);
+        set.insert(RangeInclusive::new(40, 35));
+        assert_eq!(set.ranges(), &[(0, 12), (14, 30)]);
+        set.insert(u8::MAX..=u8::MAX);
+        set.insert(13..=13);
+        assert_eq!(set.ranges(), &[(0, 30), (255, 255)]);
+        assert!(set.contains(&0) && set.contains(&17) && set.contains(&30) && set.contains(&255));
+        assert!(!set.contains(&31) && !set.contains(&254));
+        assert!(!RangeSet::<u8>::new().contains(&0));
+    }
+
+    #[test]
+    fn range_set_treats_surrogates_as_gap_in_chars() {
+        let set: RangeSet<char> = ['\u{E000}'..='\u{E0FF}', 'a'..='z', '\u{D000}'..='\u{D7FF}', 'A'..='Z'].into_iter().collect();
+        assert_eq!(set.ranges(), &[('A', 'Z'), ('a', 'z'), ('\u{D000}', '\u{E0FF}')]);
+        assert!(set.contains(&'q') && !set.contains(&'_'));
+        assert_eq!('\u{D7FF}'.successor(), Option::Some('\u{E000}'));
+        assert_eq!('\u{E000}'.predecessor(), Option::Some('\u{D7FF}'));
+        assert_eq!('\0'.predecessor(), Option::None);
+    }
+
+    type Identifiers = KeyedData<VecDeque<char>, String>;
+
+    #[test]
+    fn range_connection_matches_keys_from_set() {
+        let mut data = KeyedData::new("_ab1c-".chars().collect(), String::new());
+        let mut automaton: Automaton<u8, Identifiers, String> = Automaton::new(|| {
+            let start = new_shared_concrete_state(SimpleStateImplementation::new(1));
+            let identifier = new_shared_concrete_state(SimpleStateImplementation::new(2));
+            let push_key = |d: &mut Identifiers, k: &char| {
+                d.data.push(*k);
+                Result::Ok(())
+            };
+            let first = RangeSet::from_ranges(['a'..='z', 'A'..='Z', '_'..='_']);
+            let mut following = first.clone();
+            following.insert('0'..='9');
+            start.borrow_mut().register_connection(SimpleInterStateConnection::new_ranges(first, push_key, &identifier));
+            let handle = identifier.borrow_mut().register_connection(SimpleInterStateConnection::new_ranges(following, push_key, &identifier));
+            assert_eq!(identifier.borrow().connection(handle).and_then(|c| c.ranges()).map(|set| set.ranges().len()), Option::Some(4));
+            start
+        });
+        assert!(matches!(automaton.run(&mut data), AutomatonResult::CouldNotFindNextState(2, _)));
+        assert_eq!(data.data, "_ab1c");
+    }
+
+    #[cfg(feature = "serde")]
+    mod serde_test {
+        use crate::simple_impl::range_set::RangeSet;
+
+        #[test]
+        fn range_set_round_trip_keeps_ranges() {
+            let set = RangeSet::from_ranges(['0'..='9', 'a'..='f']);
+            let json = serde_json::to_string(&set).unwrap();
+            assert_eq!(json, r#"[["0","9"],["a","f"]]"#);
+            let restored: RangeSet<char> = serde_json::from_str(r#"[["a","f"],["0","9"],["c","g"]]"#).unwrap();
+            assert_eq!(restored, RangeSet::from_ranges(['0'..='9', 'a'..='g']));
+        }
+    }
+}
@@ -1,11 +1,114 @@
-use std::{cell::RefCell, marker::PhantomData, rc::Rc};
+use std::{cell::RefCell, fmt::{Debug, Write}, marker::PhantomData, rc::Rc};
 
-use crate::automaton_state::{convert_to_dyn_reference, AutomatonState, SharedAutomatonState};
+use crate::{automaton::NextState, automaton_state::{convert_to_dyn_reference, AutomatonState, SharedAutomatonState}, simple_impl::{matchers::Matcher, range_set::{RangeKey, RangeSet}}};
 
-/// Represents data, that can provide a key which will be used while searching for next state. Usually will use iterator
-/// based on a sequence.
-pub trait KeyProvidingData<K> {
-    fn next_key(&mut self) -> Option<K>;
+/// Key source traits are defined in [`key_provider`](crate::key_provider) and re-exported here for compatibility.
+pub use crate::key_provider::{KeyProvidingData, PeekingKeyProvidingData, PushbackKeyProvidingData};
+
+/// Predicate deciding whether a connection should be chosen for a key.
+pub type KeyMatcher<'a, K> = Box<dyn Fn(&K) -> bool + 'a>;
+
+/// Predicate deciding whether a connection should be chosen for a key and current state of the data.
+pub type GuardMatcher<'a, K, D> = Box<dyn Fn(&D, &K) -> bool + 'a>;
+
+/// Operation executed on data while changing state through a connection.
+pub type ConnectionAction<'a, K, D, E> = Box<dyn Fn(&mut D, &K) -> Result<(), E> + 'a>;
+
+/// Operation executed on data while entering or leaving a state.
+pub type StateAction<'a, D, E> = Box<dyn Fn(&mut D) -> Result<(), E> + 'a>;
+
+/// Predicate deciding whether an epsilon connection should be taken for current state of the data.
+pub type EpsilonGuard<'a, D> = Box<dyn Fn(&D) -> bool + 'a>;
+
+/// Predicate deciding whether a connection should be chosen for a key and keys following it (taken from the data).
+type MultiKeyMatcher<'a, K, D> = Box<dyn Fn(&mut D, &K) -> bool + 'a>;
+
+/// Way of deciding whether an epsilon connection should be taken.
+enum EpsilonCondition<'a, D> {
+    /// Connection is always taken.
+    Always,
+    /// Depends on the data.
+    Guard(EpsilonGuard<'a, D>),
+    /// Depends on upcoming keys peeked from the data.
+    Lookahead(Box<dyn Fn(&mut D) -> bool + 'a>),
+}
+
+impl <'a, D> EpsilonCondition<'a, D> {
+    fn is_met(&self, data: &mut D) -> bool {
+        match self {
+            EpsilonCondition::Always => true,
+            EpsilonCondition::Guard(guard) => guard(data),
+            EpsilonCondition::Lookahead(lookahead) => lookahead(data),
+        }
+    }
+}
+
+/// Way of deciding whether a connection should be chosen.
+enum ConnectionMatcher<'a, K, D> {
+    /// Depends only on the key.
+    Key(KeyMatcher<'a, K>),
+    /// Depends on the key and the data.
+    Guarded(GuardMatcher<'a, K, D>),
+    /// Depends on the key and keys following it. Following keys are consumed when matched and pushed back otherwise.
+    MultiKey(MultiKeyMatcher<'a, K, D>),
+    /// Depends on whether the key belongs to the set. Stores the lookup, so the set's key type doesn't have to be bounded
+    /// everywhere the matcher is used.
+    Ranges(RangeSet<K>, fn(&RangeSet<K>, &K) -> bool),
+    /// Depends only on the key, carries description of accepted keys.
+    Described(Matcher<'a, K>),
+}
+
+impl <'a, K, D> ConnectionMatcher<'a, K, D> {
+    fn matches(&self, data: &mut D, key: &K) -> bool {
+        match self {
+            ConnectionMatcher::Key(matcher) => matcher(key),
+            ConnectionMatcher::Guarded(guard) => guard(data, key),
+            ConnectionMatcher::MultiKey(matcher) => matcher(data, key),
+            ConnectionMatcher::Ranges(set, contains) => contains(set, key),
+            ConnectionMatcher::Described(matcher) => matcher.matches(key),
+        }
+    }
+
+    /// Returns name of the kind of matcher (with description if available), used when listing connections.
+    fn describe(&self) -> String {
+        match self {
+            ConnectionMatcher::Key(_) => String::from("key"),
+            ConnectionMatcher::Guarded(_) => String::from("guarded"),
+            ConnectionMatcher::MultiKey(_) => String::from("multi-key"),
+            ConnectionMatcher::Ranges(_, _) => String::from("ranges"),
+            ConnectionMatcher::Described(matcher) => format!("key {}", matcher.description()),
+        }
+    }
+}
+
+/// Operation receiving the data, the key and a function that has to be executed before anything else is done with the
+/// data once the connection is matched. Returns `None` if the connection doesn't match the key.
+type CapturingAction<'a, K, D, E> = Box<dyn Fn(&mut D, &K, &mut dyn FnMut(&mut D) -> Result<(), E>) -> Option<Result<(), E>> + 'a>;
+
+/// Way of matching a key and executing operation of a connection.
+enum ConnectionBody<'a, K, D, E> {
+    /// Matcher deciding whether the connection is chosen and operation executed afterwards.
+    Matched(ConnectionMatcher<'a, K, D>, ConnectionAction<'a, K, D, E>),
+    /// Matcher extracting a value from the key combined with the operation receiving that value, so the value is passed
+    /// directly from one to the other.
+    Capturing(CapturingAction<'a, K, D, E>),
+}
+
+impl <'a, K, D, E> ConnectionBody<'a, K, D, E> {
+    /// Returns name of the kind of matcher (with description if available), used when listing connections.
+    fn describe(&self) -> String {
+        match self {
+            ConnectionBody::Matched(matcher, _) => matcher.describe(),
+            ConnectionBody::Capturing(_) => String::from("capturing"),
+        }
+    }
+}
+
+/// Returns consumed keys to the data, so they will be provided again in the same order.
+fn push_back_keys<K, D: PushbackKeyProvidingData<K>>(data: &mut D, keys: Vec<K>) {
+    for key in keys.into_iter().rev() {
+        data.push_back_key(key);
+    }
 }
 
 ///
@@ -14,20 +117,27 @@ pub trait KeyProvidingData<K> {
 /// executed while changing to matched next state.
 /// 
 /// * `matcher` - Defines whether this connection should be chosen for a specified key. It's up to the user to ensure
-/// that connections don't have intersecting matchers. The first connection matched for a key will always be used.
+///   that connections don't have intersecting matchers. The first connection matched for a key will always be used.
+///   Guarded connections use a matcher which also receives the data, so the choice can depend on the data gathered so far.
+///   Sequence and slice connections match a fixed number of keys, starting with the key provided to the state. Following
+///   keys are consumed only if the whole sequence matches, otherwise they are pushed back to the data. Range connections
+///   match keys belonging to a [`RangeSet`], which (unlike other matchers) can be inspected. Capturing connections use
+///   a matcher extracting a value from the key, which is then passed to the operation.
 /// * `exec_function` - Operation that will be executing while changing state.
 /// * `connected_state` - State that will be returned if this connection is matched. Can be the same state that this
-/// connection will be assigned to.
+///   connection will be assigned to.
+/// * `priority` - Connections with higher priority are checked first. Connections with equal priority are checked in the
+///   order of registration. Defaults to `0`.
 pub struct SimpleInterStateConnection<'a, K, Id, D, E> where Id: Copy + 'a, K: 'a, D: 'a, E: 'a {
-    matcher: Box<dyn Fn(&K) -> bool + 'a>,
-    exec_function: Box<dyn Fn(&mut D, &K) -> Result<(), E> + 'a>,
+    body: ConnectionBody<'a, K, D, E>,
     connected_state: SharedAutomatonState<'a, Id, D, E>,
+    priority: i32,
 }
 
 impl <'a, K, Id, D, E> SimpleInterStateConnection<'a, K, Id, D, E> where Id: Copy {
     /// Creates new connection with specified matcher and a procedure that will be executed when this connection is matched.
     pub fn new<M: Fn(&K) -> bool + 'a, FExec: Fn(&mut D, &K) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(matcher: M, exec_function: FExec, next_state: &Rc<RefCell<S>>) -> Self {
-        Self { matcher: Box::new(matcher), exec_function: Box::new(exec_function), connected_state: convert_to_dyn_reference(Rc::clone(next_state)) }
+        Self::from_parts(ConnectionMatcher::Key(Box::new(matcher)), Box::new(exec_function), next_state)
     }
 
     /// Creates new connection with specified matcher. Does nothing when matched (designed to be used with intermediate states).
@@ -35,20 +145,292 @@ impl <'a, K, Id, D, E> SimpleInterStateConnection<'a, K, Id, D, E> where Id: Cop
         Self::new(matcher, Self::do_nothing, next_state)
     }
 
+    /// Creates new connection with specified guard and a procedure that will be executed when this connection is matched.
+    /// Guard receives the data (before the procedure is executed) along with the key.
+    pub fn new_guarded<G: Fn(&D, &K) -> bool + 'a, FExec: Fn(&mut D, &K) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(guard: G, exec_function: FExec, next_state: &Rc<RefCell<S>>) -> Self {
+        Self::from_parts(ConnectionMatcher::Guarded(Box::new(guard)), Box::new(exec_function), next_state)
+    }
+
+    /// Creates new connection with specified guard. Does nothing when matched.
+    pub fn new_guarded_no_action<G: Fn(&D, &K) -> bool + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(guard: G, next_state: &Rc<RefCell<S>>) -> Self {
+        Self::new_guarded(guard, Self::do_nothing, next_state)
+    }
+
+    /// Creates new connection matching given sequence of keys and a procedure that will be executed when this connection is
+    /// matched. Procedure receives the first key of the sequence. Empty sequence never matches.
+    pub fn new_sequence<FExec: Fn(&mut D, &K) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(sequence: Vec<K>, exec_function: FExec, next_state: &Rc<RefCell<S>>) -> Self
+    where K: PartialEq, D: PushbackKeyProvidingData<K> {
+        let matcher = move |data: &mut D, key: &K| {
+            let Option::Some((first, rest)) = sequence.split_first() else {
+                return false;
+            };
+            if first != key {
+                return false;
+            }
+            let mut consumed = Vec::with_capacity(rest.len());
+            for expected in rest {
+                match data.next_key() {
+                    Option::Some(next_key) => {
+                        let matched = next_key == *expected;
+                        consumed.push(next_key);
+                        if !matched {
+                            push_back_keys(data, consumed);
+                            return false;
+                        }
+                    },
+                    Option::None => {
+                        push_back_keys(data, consumed);
+                        return false;
+                    },
+                }
+            }
+            true
+        };
+        Self::from_parts(ConnectionMatcher::MultiKey(Box::new(matcher)), Box::new(exec_function), next_state)
+    }
+
+    /// Creates new connection matching given sequence of keys. Does nothing when matched.
+    pub fn new_sequence_no_action<S: AutomatonState<'a, Id, D, E> + 'a>(sequence: Vec<K>, next_state: &Rc<RefCell<S>>) -> Self
+    where K: PartialEq, D: PushbackKeyProvidingData<K> {
+        Self::new_sequence(sequence, Self::do_nothing, next_state)
+    }
+
+    /// Creates new connection matching `length` keys with given predicate and a procedure that will be executed when this
+    /// connection is matched. Procedure receives the first key of the matched slice. Slice doesn't match if the input ends
+    /// before `length` keys are read. Slice of length `0` never matches (the key provided to the state is always a part of
+    /// the slice), so the matcher isn't called then.
+    pub fn new_slice<M: Fn(&[K]) -> bool + 'a, FExec: Fn(&mut D, &K) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(length: usize, matcher: M, exec_function: FExec, next_state: &Rc<RefCell<S>>) -> Self
+    where K: Clone, D: PushbackKeyProvidingData<K> {
+        let slice_matcher = move |data: &mut D, key: &K| {
+            let mut keys = vec![key.clone()];
+            while keys.len() < length {
+                match data.next_key() {
+                    Option::Some(next_key) => keys.push(next_key),
+                    Option::None => break,
+                }
+            }
+            if keys.len() == length && matcher(&keys) {
+                return true;
+            }
+            push_back_keys(data, keys.split_off(1));
+            false
+        };
+        Self::from_parts(ConnectionMatcher::MultiKey(Box::new(slice_matcher)), Box::new(exec_function), next_state)
+    }
+
+    /// Creates new connection matching `length` keys with given predicate. Does nothing when matched.
+    pub fn new_slice_no_action<M: Fn(&[K]) -> bool + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(length: usize, matcher: M, next_state: &Rc<RefCell<S>>) -> Self
+    where K: Clone, D: PushbackKeyProvidingData<K> {
+        Self::new_slice(length, matcher, Self::do_nothing, next_state)
+    }
+
+    /// Creates new connection matching keys from the set and a procedure that will be executed when this connection is matched.
+    pub fn new_ranges<FExec: Fn(&mut D, &K) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(ranges: RangeSet<K>, exec_function: FExec, next_state: &Rc<RefCell<S>>) -> Self
+    where K: RangeKey {
+        Self::from_parts(ConnectionMatcher::Ranges(ranges, RangeSet::contains), Box::new(exec_function), next_state)
+    }
+
+    /// Creates new connection matching keys from the set. Does nothing when matched.
+    pub fn new_ranges_no_action<S: AutomatonState<'a, Id, D, E> + 'a>(ranges: RangeSet<K>, next_state: &Rc<RefCell<S>>) -> Self
+    where K: RangeKey {
+        Self::new_ranges(ranges, Self::do_nothing, next_state)
+    }
+
+    /// Creates new connection with specified [`Matcher`] and a procedure that will be executed when this connection is
+    /// matched. Description of the matcher is kept for diagnostics.
+    pub fn new_matcher<FExec: Fn(&mut D, &K) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(matcher: Matcher<'a, K>, exec_function: FExec, next_state: &Rc<RefCell<S>>) -> Self {
+        Self::from_parts(ConnectionMatcher::Described(matcher), Box::new(exec_function), next_state)
+    }
+
+    /// Creates new connection with specified [`Matcher`]. Does nothing when matched.
+    pub fn new_matcher_no_action<S: AutomatonState<'a, Id, D, E> + 'a>(matcher: Matcher<'a, K>, next_state: &Rc<RefCell<S>>) -> Self {
+        Self::new_matcher(matcher, Self::do_nothing, next_state)
+    }
+
+    /// Returns description of keys matched by this connection. Returns `None` if the connection wasn't created with a
+    /// [`Matcher`].
+    pub fn description(&self) -> Option<&str> {
+        match &self.body {
+            ConnectionBody::Matched(ConnectionMatcher::Described(matcher), _) => Option::Some(matcher.description()),
+            _ => Option::None,
+        }
+    }
+
+    /// Creates new connection with a matcher extracting a value from the key and a procedure that will be executed when this
+    /// connection is matched. Connection is matched if the matcher returns `Some`, the value is then passed to the procedure
+    /// (so it doesn't have to be computed again).
+    pub fn new_capturing<T: 'a, M: Fn(&K) -> Option<T> + 'a, FExec: Fn(&mut D, &K, T) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(matcher: M, exec_function: FExec, next_state: &Rc<RefCell<S>>) -> Self {
+        let capturing = move |data: &mut D, key: &K, before_exec: &mut dyn FnMut(&mut D) -> Result<(), E>| {
+            let value = matcher(key)?;
+            Option::Some(before_exec(data).and_then(|_| exec_function(data, key, value)))
+        };
+        Self { body: ConnectionBody::Capturing(Box::new(capturing)), connected_state: convert_to_dyn_reference(Rc::clone(next_state)), priority: 0 }
+    }
+
+    /// Sets priority of this connection. Connections with higher priority are checked first.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Returns priority of this connection.
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Returns set of keys matched by this connection. Returns `None` if the connection uses any other kind of matcher.
+    pub fn ranges(&self) -> Option<&RangeSet<K>> {
+        match &self.body {
+            ConnectionBody::Matched(ConnectionMatcher::Ranges(set, _), _) => Option::Some(set),
+            _ => Option::None,
+        }
+    }
+
+    /// Checks whether this connection should be chosen for a key. If it should, executes `before_exec` (e.g. exit action
+    /// of the state) and assigned procedure, then returns connected state. Returns `None` if the connection doesn't match.
+    pub(crate) fn try_execute(&self, data: &mut D, key: &K, before_exec: &mut dyn FnMut(&mut D) -> Result<(), E>) -> Option<Result<SharedAutomatonState<'a, Id, D, E>, E>> {
+        let executed = match &self.body {
+            ConnectionBody::Matched(matcher, exec_function) => {
+                if !matcher.matches(data, key) {
+                    return Option::None;
+                }
+                before_exec(data).and_then(|_| exec_function(data, key))
+            },
+            ConnectionBody::Capturing(capturing) => capturing(data, key, before_exec)?,
+        };
+        Option::Some(executed.map(|_| Rc::clone(&self.connected_state)))
+    }
+
+    fn from_parts<S: AutomatonState<'a, Id, D, E> + 'a>(matcher: ConnectionMatcher<'a, K, D>, exec_function: ConnectionAction<'a, K, D, E>, next_state: &Rc<RefCell<S>>) -> Self {
+        Self { body: ConnectionBody::Matched(matcher, exec_function), connected_state: convert_to_dyn_reference(Rc::clone(next_state)), priority: 0 }
+    }
+
     /// Does nothing
     fn do_nothing(_:&mut D, _:&K) -> Result<(), E> {
         Result::Ok(())
     }
 }
 
+/// Connection between two states that doesn't consume a key (epsilon transition). Epsilon connections are checked before
+/// a key is taken from the data, so a state with an epsilon connection that is always taken works as a step that executes
+/// an operation and passes control to the connected state.
+///
+/// * `condition` - Defines whether this connection should be taken, based either on current state of the data (guard) or
+///   on upcoming keys peeked from the data without consuming them (lookahead).
+/// * `exec_function` - Operation that will be executing while changing state.
+/// * `connected_state` - State that will be returned if this connection is taken.
+pub struct EpsilonConnection<'a, Id, D, E> where Id: Copy + 'a, D: 'a, E: 'a {
+    condition: EpsilonCondition<'a, D>,
+    exec_function: StateAction<'a, D, E>,
+    connected_state: SharedAutomatonState<'a, Id, D, E>,
+}
+
+impl <'a, Id, D, E> EpsilonConnection<'a, Id, D, E> where Id: Copy {
+    /// Creates new connection that is always taken. Procedure will be executed while changing state.
+    pub fn new<FExec: Fn(&mut D) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(exec_function: FExec, next_state: &Rc<RefCell<S>>) -> Self {
+        Self { condition: EpsilonCondition::Always, exec_function: Box::new(exec_function), connected_state: convert_to_dyn_reference(Rc::clone(next_state)) }
+    }
+
+    /// Creates new connection that is always taken. Does nothing when taken.
+    pub fn new_no_action<S: AutomatonState<'a, Id, D, E> + 'a>(next_state: &Rc<RefCell<S>>) -> Self {
+        Self::new(|_| Result::Ok(()), next_state)
+    }
+
+    /// Creates new connection that is taken only if guard accepts current state of the data. Procedure will be executed
+    /// while changing state.
+    pub fn new_guarded<G: Fn(&D) -> bool + 'a, FExec: Fn(&mut D) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(guard: G, exec_function: FExec, next_state: &Rc<RefCell<S>>) -> Self {
+        Self { condition: EpsilonCondition::Guard(Box::new(guard)), exec_function: Box::new(exec_function), connected_state: convert_to_dyn_reference(Rc::clone(next_state)) }
+    }
+
+    /// Creates new connection that is taken only if guard accepts current state of the data. Does nothing when taken.
+    pub fn new_guarded_no_action<G: Fn(&D) -> bool + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(guard: G, next_state: &Rc<RefCell<S>>) -> Self {
+        Self::new_guarded(guard, |_| Result::Ok(()), next_state)
+    }
+
+    /// Creates new connection that is taken only if matcher accepts upcoming keys. Keys are peeked from the data, so they
+    /// will still be available for the connected state. Procedure will be executed while changing state.
+    ///
+    /// * `length` - Number of keys passed to the matcher. Matcher receives fewer keys if the input ends earlier.
+    pub fn new_lookahead<K: 'a, M: Fn(&[K]) -> bool + 'a, FExec: Fn(&mut D) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(length: usize, matcher: M, exec_function: FExec, next_state: &Rc<RefCell<S>>) -> Self
+    where D: PeekingKeyProvidingData<K> {
+        let lookahead = move |data: &mut D| {
+            let keys: Vec<K> = (0..length).map_while(|offset| data.peek_key(offset)).collect();
+            matcher(&keys)
+        };
+        Self { condition: EpsilonCondition::Lookahead(Box::new(lookahead)), exec_function: Box::new(exec_function), connected_state: convert_to_dyn_reference(Rc::clone(next_state)) }
+    }
+
+    /// Creates new connection that is taken only if matcher accepts upcoming keys. Does nothing when taken.
+    pub fn new_lookahead_no_action<K: 'a, M: Fn(&[K]) -> bool + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(length: usize, matcher: M, next_state: &Rc<RefCell<S>>) -> Self
+    where D: PeekingKeyProvidingData<K> {
+        Self::new_lookahead(length, matcher, |_| Result::Ok(()), next_state)
+    }
+}
+
+/// Takes the first epsilon connection whose condition is met, executing the exit action of the state and the operation of
+/// the connection. Returns `None` if no epsilon connection is taken.
+pub(crate) fn take_epsilon_connection<'a, Id, D, E>(connections: &[EpsilonConnection<'a, Id, D, E>], exit_action: Option<&StateAction<'a, D, E>>, data: &mut D) -> Option<Result<NextState<'a, Id, D, E>, E>> where Id: Copy {
+    let c = connections.iter().find(|c| c.condition.is_met(data))?;
+    let executed = exit_action.map_or(Result::Ok(()), |exit_action| exit_action(data)).and_then(|_| (c.exec_function)(data));
+    Option::Some(executed.map(|_| {
+        let next_state = Rc::clone(&c.connected_state);
+        if matches!(c.condition, EpsilonCondition::Always) {
+            NextState::Epsilon(next_state)
+        } else {
+            NextState::GuardedEpsilon(next_state)
+        }
+    }))
+}
+
+/// Identifies connection registered on a [`SimpleStateImplementation`]. Handles are unique only within the state that
+/// returned them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionHandle(pub(crate) usize);
+
 /// AutomatonState implementating struct which simplifies state definition by managing list of defined connections. 
 /// Depends on data for providing next key. This key is then used to match a connection from the defined list.
 /// Each state has an assigned identifier which is used to inform which state did the automaton stop on.
 /// Identifier is copied to the result meaning it has to implement the *Copy* trait.
+///
+/// Keys can be provided either by the data itself or by a separate key source combined with the data in a
+/// [`KeyedData`](crate::simple_impl::key_sources::KeyedData) (see `Automaton::run_with_keys`). Actions and fallback
+/// connection of such state can work on the user data only (`set_entry_action_on_data` and similar methods).
+///
+/// Connections are checked in the order of their priorities (highest first), connections with equal priority are checked
+/// in the order of registration. Order of evaluation can be listed with [`SimpleStateImplementation::describe_evaluation_order`].
+///
+/// Connections can be listed, removed, replaced and reordered through handles returned while registering them. Automaton
+/// borrows the current state while matching a connection, so connections of the current state can't be modified from
+/// inside of an operation executed by one of its connections (this will cause a panic). Other states can be modified at
+/// any time and the changes will be visible the next time the automaton enters them, this also applies to runs paused
+/// with `Automaton::run_steps`.
+///
+/// State can have entry and exit actions. When a connection is matched, the exit action of this state is executed first,
+/// then the operation assigned to the connection and finally the entry action of the connected state (also when the
+/// connection leads back to the same state). Entry action is also executed when the run starts on this state. Errors
+/// returned by these actions stop the automaton the same way as errors returned by connection operations.
+///
+/// State can also have a fallback connection which is taken only when none of the registered connections matches a key
+/// (instead of stopping the automaton).
+///
+/// Epsilon connections are checked (in the order of registration) before a key is taken from the data. If one of them is
+/// taken, the key stays in the data for the connected state. Automaton stops with `AutomatonResult::EpsilonCycle` if
+/// consecutive epsilon connections lead back to a state visited since the last consumed key, but only if each of them
+/// was created without a guard or lookahead (also when it was taken because guarded connections registered before it
+/// weren't). Loops passing through a guarded connection are left for their guards to end, up to the limit set with
+/// `Automaton::with_epsilon_limit`.
+///
+/// Key for which no connection was found is passed to [`KeyProvidingData::restore_unmatched_key`] before the automaton
+/// stops, so data that can take keys back keeps it for another automaton (or the next run). Other data drops the key.
 pub struct SimpleStateImplementation<'a, K, Id, D, E> where D: KeyProvidingData<K>, Id: Copy{
     _phantom: PhantomData<D>,
     id: Id,
-    next_states: Vec<SimpleInterStateConnection<'a, K, Id, D, E>>,
+    next_states: Vec<(ConnectionHandle, SimpleInterStateConnection<'a, K, Id, D, E>)>,
+    next_handle: usize,
+    fallback_connection: Option<SimpleInterStateConnection<'a, K, Id, D, E>>,
+    epsilon_connections: Vec<EpsilonConnection<'a, Id, D, E>>,
+    entry_action: Option<StateAction<'a, D, E>>,
+    exit_action: Option<StateAction<'a, D, E>>,
 }
 
 impl <'a, K, Id, D, E> SimpleStateImplementation<'a, K, Id, D, E> where D: KeyProvidingData<K>, Id: Copy {
@@ -56,13 +438,141 @@ impl <'a, K, Id, D, E> SimpleStateImplementation<'a, K, Id, D, E> where D: KeyPr
     /// 
     /// * `id` - Identifier of this state which will be copied into result when automaton stops on this state.
     pub fn new(id: Id) -> Self {
-        Self { _phantom: PhantomData{}, next_states: Vec::new(), id}
+        Self { _phantom: PhantomData{}, next_states: Vec::new(), id, next_handle: 0, fallback_connection: Option::None, epsilon_connections: Vec::new(), entry_action: Option::None, exit_action: Option::None}
+    }
+
+    /// Adds connection which will be checked before taking a key from the data.
+    pub fn register_epsilon_connection(&mut self, connection: EpsilonConnection<'a, Id, D, E>) {
+        self.epsilon_connections.push(connection);
+    }
+
+    /// Sets connection that will be used when no registered connection matches a key. Procedure will be executed when
+    /// this connection is used. Replaces previously set fallback connection.
+    pub fn set_fallback_connection<FExec: Fn(&mut D, &K) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, D, E> + 'a>(&mut self, exec_function: FExec, next_state: &Rc<RefCell<S>>) {
+        self.fallback_connection = Option::Some(SimpleInterStateConnection::new(|_| true, exec_function, next_state));
+    }
+
+    /// Sets connection that will be used when no registered connection matches a key. Does nothing when used. Replaces
+    /// previously set fallback connection.
+    pub fn set_fallback_connection_no_action<S: AutomatonState<'a, Id, D, E> + 'a>(&mut self, next_state: &Rc<RefCell<S>>) {
+        self.fallback_connection = Option::Some(SimpleInterStateConnection::new_no_action(|_| true, next_state));
+    }
+
+    /// Removes fallback connection. Returns removed connection or `None` if it wasn't set.
+    pub fn remove_fallback_connection(&mut self) -> Option<SimpleInterStateConnection<'a, K, Id, D, E>> {
+        self.fallback_connection.take()
+    }
+
+    /// Sets operation executed whenever this state becomes active. Replaces previously set entry action.
+    pub fn set_entry_action<FEntry: Fn(&mut D) -> Result<(), E> + 'a>(&mut self, entry_action: FEntry) {
+        self.entry_action = Option::Some(Box::new(entry_action));
+    }
+
+    /// Sets operation executed whenever a connection of this state is matched, before the operation of that connection.
+    /// Replaces previously set exit action.
+    pub fn set_exit_action<FExit: Fn(&mut D) -> Result<(), E> + 'a>(&mut self, exit_action: FExit) {
+        self.exit_action = Option::Some(Box::new(exit_action));
     }
 
-    /// Adds connection to possible next states of current state.
-    pub fn register_connection(&mut self, connection: SimpleInterStateConnection<'a, K, Id, D, E>) -> () 
+    /// Adds connection to possible next states of current state. Returned handle can be used for modifying this connection later.
+    pub fn register_connection(&mut self, connection: SimpleInterStateConnection<'a, K, Id, D, E>) -> ConnectionHandle
     {
-        self.next_states.push(connection);
+        let handle = ConnectionHandle(self.next_handle);
+        self.next_handle += 1;
+        self.insert_by_priority(handle, connection);
+        handle
+    }
+
+    /// Lists handles of registered connections in the order in which they are matched.
+    pub fn connections(&self) -> Vec<ConnectionHandle> {
+        self.next_states.iter().map(|(handle, _)| *handle).collect()
+    }
+
+    /// Returns connection with provided handle. Returns `None` if the connection was removed.
+    pub fn connection(&self, handle: ConnectionHandle) -> Option<&SimpleInterStateConnection<'a, K, Id, D, E>> {
+        self.connection_position(handle).map(|position| &self.next_states[position].1)
+    }
+
+    /// Removes connection. Returns removed connection or `None` if this state doesn't have a connection with given handle.
+    pub fn remove_connection(&mut self, handle: ConnectionHandle) -> Option<SimpleInterStateConnection<'a, K, Id, D, E>> {
+        let position = self.connection_position(handle)?;
+        Option::Some(self.next_states.remove(position).1)
+    }
+
+    /// Replaces connection keeping its handle and position. If the new connection has a different priority, it's moved
+    /// after connections with the same priority instead. Returns replaced connection or `None` if this state doesn't have
+    /// a connection with given handle (provided connection is not registered in that case).
+    pub fn replace_connection(&mut self, handle: ConnectionHandle, connection: SimpleInterStateConnection<'a, K, Id, D, E>) -> Option<SimpleInterStateConnection<'a, K, Id, D, E>> {
+        let position = self.connection_position(handle)?;
+        if self.next_states[position].1.priority == connection.priority {
+            return Option::Some(std::mem::replace(&mut self.next_states[position].1, connection));
+        }
+        let (_, replaced) = self.next_states.remove(position);
+        self.insert_by_priority(handle, connection);
+        Option::Some(replaced)
+    }
+
+    /// Moves connection to given position in matching order. Connection stays among connections with the same priority,
+    /// so positions outside of that group move it to the beginning or the end of the group. Returns `false` if this state
+    /// doesn't have a connection with given handle.
+    pub fn move_connection(&mut self, handle: ConnectionHandle, position: usize) -> bool {
+        if let Option::Some(current_position) = self.connection_position(handle) {
+            let connection = self.next_states.remove(current_position);
+            let priority = connection.1.priority;
+            let group_start = self.next_states.partition_point(|(_, c)| c.priority > priority);
+            let group_end = self.next_states.partition_point(|(_, c)| c.priority >= priority);
+            self.next_states.insert(position.clamp(group_start, group_end), connection);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Lists epsilon connections, registered connections and the fallback connection in the order in which they are
+    /// checked, along with identifiers of connected states. Designed for debugging. Can be called while this state is
+    /// mutably borrowed, connected states that are mutably borrowed (other than this state) are listed as `<borrowed>`.
+    pub fn describe_evaluation_order(&self) -> String where Id: Debug {
+        let mut description = String::new();
+        let _ = writeln!(description, "state {:?}", self.id);
+        for (position, connection) in self.epsilon_connections.iter().enumerate() {
+            let _ = writeln!(description, "  epsilon #{} -> {}", position, self.describe_target(&connection.connected_state));
+        }
+        for (handle, connection) in &self.next_states {
+            let _ = writeln!(description, "  {:?} priority {} {} -> {}", handle, connection.priority, connection.body.describe(), self.describe_target(&connection.connected_state));
+        }
+        if let Option::Some(connection) = &self.fallback_connection {
+            let _ = writeln!(description, "  fallback -> {}", self.describe_target(&connection.connected_state));
+        }
+        description
+    }
+
+    /// Returns identifier of the connected state without borrowing this state again.
+    fn describe_target(&self, state: &SharedAutomatonState<'a, Id, D, E>) -> String where Id: Debug {
+        if state.as_ptr() as *const () == self as *const Self as *const () {
+            return format!("{:?}", self.id);
+        }
+        match state.try_borrow() {
+            Result::Ok(state) => format!("{:?}", state.get_id_owned()),
+            Result::Err(_) => String::from("<borrowed>"),
+        }
+    }
+
+    /// Executes exit action if it was set.
+    fn execute_exit(&self, data: &mut D) -> Result<(), E> {
+        if let Option::Some(exit_action) = &self.exit_action {
+            exit_action(data)?;
+        }
+        Result::Ok(())
+    }
+
+    fn connection_position(&self, handle: ConnectionHandle) -> Option<usize> {
+        self.next_states.iter().position(|(h, _)| *h == handle)
+    }
+
+    /// Inserts connection after all connections with the same or higher priority.
+    fn insert_by_priority(&mut self, handle: ConnectionHandle, connection: SimpleInterStateConnection<'a, K, Id, D, E>) {
+        let position = self.next_states.partition_point(|(_, c)| c.priority >= connection.priority);
+        self.next_states.insert(position, (handle, connection));
     }
 }
 
@@ -77,22 +587,35 @@ impl<'a, K, Id, D, E> AutomatonState<'a, Id, D, E> for SimpleStateImplementation
         &self.id
     }
 
-    /// Finds connection by popping key from key iterator. Executes assigned function and returns next state if everything goes
-    /// alright. 
+    /// Takes first epsilon connection accepting the data or finds connection by popping key from key iterator (using fallback
+    /// connection if no connection matches). Executes assigned function and returns next state if everything goes alright.
     fn execute_next_connection(&self, data: &mut D) -> Result<crate::automaton::NextState<'a, Id, D, E>, E> {
+        if let Option::Some(epsilon_result) = take_epsilon_connection(&self.epsilon_connections, self.exit_action.as_ref(), data) {
+            return epsilon_result;
+        }
         let next_key = data.next_key();
         if let Option::Some(k) = next_key {
-            for c in &self.next_states {
-                if (c.matcher)(&k) {
-                    (c.exec_function)(data, &k)?;
-                    return Result::Ok(crate::automaton::NextState::Continue(Rc::clone(&c.connected_state)));
-                }
+            let mut exit = |data: &mut D| self.execute_exit(data);
+            let executed = self.next_states.iter()
+                .find_map(|(_, c)| c.try_execute(data, &k, &mut exit))
+                .or_else(|| self.fallback_connection.as_ref().and_then(|c| c.try_execute(data, &k, &mut exit)));
+            if let Option::Some(next_state) = executed {
+                return Result::Ok(crate::automaton::NextState::Continue(next_state?));
             }
+            data.restore_unmatched_key(k);
             Result::Ok(crate::automaton::NextState::NotFound)
         } else {
             Result::Ok(crate::automaton::NextState::ProcessEnded)
         }
     }
+
+    /// Executes entry action if it was set.
+    fn execute_entry(&self, data: &mut D) -> Result<(), E> {
+        if let Option::Some(entry_action) = &self.entry_action {
+            entry_action(data)?;
+        }
+        Result::Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -131,7 +654,9 @@ mod test {
     }
 
     mod automaton_test {
-        use crate::{automaton::{Automaton, AutomatonResult}, automaton_state::new_shared_concrete_state, simple_impl::simple_state::{test::TestData, SimpleInterStateConnection, SimpleStateImplementation}};
+        use std::{cell::RefCell, rc::Rc};
+
+        use crate::{automaton::{Automaton, AutomatonResult}, automaton_state::{convert_to_dyn_reference, new_shared_concrete_state}, simple_impl::simple_state::{test::TestData, EpsilonConnection, KeyProvidingData, PushbackKeyProvidingData, SimpleInterStateConnection, SimpleStateImplementation}};
 
         #[test]
         fn automaton_with_simple_states_works() -> () {
@@ -169,7 +694,405 @@ mod test {
             });
             let run_result: AutomatonResult<u32, String> = automaton.run(&mut data);
             assert_eq!(data.data(), "");
-            assert!(matches!(run_result, AutomatonResult::CouldNotFindNextState(1)));
+            assert!(matches!(run_result, AutomatonResult::CouldNotFindNextState(1, _)));
+        }
+
+        fn appending_connection<'a>(state: &Rc<RefCell<SimpleStateImplementation<'a, u8, u8, TestData, String>>>, text: &'static str) -> SimpleInterStateConnection<'a, u8, u8, TestData, String> {
+            SimpleInterStateConnection::new(|_| true, move |d: &mut TestData, _| {
+                d.append_text(text);
+                Result::Ok(())
+            }, state)
+        }
+
+        #[test]
+        fn simple_state_connections_can_be_modified_between_runs() {
+            let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+            let a = state.borrow_mut().register_connection(appending_connection(&state, "a"));
+            let b = state.borrow_mut().register_connection(appending_connection(&state, "b"));
+            assert_eq!(state.borrow().connections(), vec![a, b]);
+            let start = convert_to_dyn_reference(Rc::clone(&state));
+            let mut automaton = Automaton::new(move || Rc::clone(&start));
+
+            let mut data = TestData::new(1, 3);
+            automaton.run(&mut data);
+            assert_eq!(data.data(), "aa");
+
+            assert!(state.borrow_mut().move_connection(b, 0));
+            assert_eq!(state.borrow().connections(), vec![b, a]);
+            let mut data = TestData::new(1, 3);
+            automaton.run(&mut data);
+            assert_eq!(data.data(), "bb");
+
+            let replacement = appending_connection(&state, "c");
+            assert!(state.borrow_mut().replace_connection(b, replacement).is_some());
+            assert_eq!(state.borrow().connections(), vec![b, a]);
+            let mut data = TestData::new(1, 3);
+            automaton.run(&mut data);
+            assert_eq!(data.data(), "cc");
+
+            assert!(state.borrow_mut().remove_connection(b).is_some());
+            assert_eq!(state.borrow().connections(), vec![a]);
+            let mut data = TestData::new(1, 3);
+            automaton.run(&mut data);
+            assert_eq!(data.data(), "aa");
+
+            assert!(state.borrow_mut().remove_connection(b).is_none());
+            assert!(!state.borrow_mut().move_connection(b, 0));
+            let replacement = appending_connection(&state, "d");
+            assert!(state.borrow_mut().replace_connection(b, replacement).is_none());
+        }
+
+        #[test]
+        fn connections_are_checked_by_priority() {
+            let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+            let a = state.borrow_mut().register_connection(appending_connection(&state, "a"));
+            let b = state.borrow_mut().register_connection(appending_connection(&state, "b").with_priority(5));
+            let c = state.borrow_mut().register_connection(appending_connection(&state, "c").with_priority(-1));
+            let d = state.borrow_mut().register_connection(appending_connection(&state, "d").with_priority(5));
+            assert_eq!(state.borrow().connections(), vec![b, d, a, c]);
+            assert!(state.borrow_mut().move_connection(c, 0));
+            assert!(state.borrow_mut().move_connection(d, 0));
+            assert_eq!(state.borrow().connections(), vec![d, b, a, c]);
+            state.borrow_mut().set_fallback_connection_no_action(&state);
+            assert_eq!(state.borrow().describe_evaluation_order(), format!("state 1\n  {:?} priority 5 key -> 1\n  {:?} priority 5 key -> 1\n  {:?} priority 0 key -> 1\n  {:?} priority -1 key -> 1\n  fallback -> 1\n", d, b, a, c));
+
+            let start = convert_to_dyn_reference(Rc::clone(&state));
+            let mut automaton = Automaton::new(move || Rc::clone(&start));
+            let mut data = TestData::new(1, 2);
+            automaton.run(&mut data);
+            assert_eq!(data.data(), "d");
+
+            let replacement = appending_connection(&state, "e").with_priority(1);
+            assert!(state.borrow_mut().replace_connection(c, replacement).is_some());
+            assert_eq!(state.borrow().connections(), vec![d, b, c, a]);
+            assert!(state.borrow_mut().remove_connection(d).is_some());
+            assert!(state.borrow_mut().remove_connection(b).is_some());
+            let mut data = TestData::new(1, 2);
+            automaton.run(&mut data);
+            assert_eq!(data.data(), "e");
+        }
+
+        #[test]
+        fn evaluation_order_can_be_described_while_states_are_borrowed() {
+            let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+            let other = new_shared_concrete_state(SimpleStateImplementation::new(2));
+            state.borrow_mut().register_epsilon_connection(EpsilonConnection::new_no_action(&state));
+            let a = state.borrow_mut().register_connection(appending_connection(&other, "a"));
+            state.borrow_mut().set_fallback_connection_no_action(&state);
+            let other_borrow = other.borrow_mut();
+            assert_eq!(state.borrow_mut().describe_evaluation_order(), format!("state 1\n  epsilon #0 -> 1\n  {:?} priority 0 key -> <borrowed>\n  fallback -> 1\n", a));
+            drop(other_borrow);
+            assert_eq!(state.borrow_mut().describe_evaluation_order(), format!("state 1\n  epsilon #0 -> 1\n  {:?} priority 0 key -> 2\n  fallback -> 1\n", a));
+        }
+
+        #[test]
+        fn guarded_connections_depend_on_data() {
+            let mut data = TestData::new(1, 8);
+            let mut automaton = Automaton::new(|| {
+                let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new_guarded(|d: &TestData, _| d.data().len() >= 3, |d: &mut TestData, _| {
+                    d.append_text("b");
+                    Result::Ok(())
+                }, &state));
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new(|k| k % 2 == 1, |d: &mut TestData, _| {
+                    d.append_text("a");
+                    Result::Ok(())
+                }, &state));
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new_guarded_no_action(|d: &TestData, _| d.data().len() < 3, &state));
+                state
+            });
+            let run_result: AutomatonResult<u8, String> = automaton.run(&mut data);
+            assert_eq!(data.data(), "aaabb");
+            assert!(matches!(run_result, AutomatonResult::EmptyIter(1)));
+        }
+
+        #[test]
+        // `is_multiple_of` requires Rust 1.87.
+        #[allow(clippy::manual_is_multiple_of)]
+        fn capturing_connections_pass_extracted_values() {
+            let mut data = TestData::new(1, 8);
+            let mut automaton = Automaton::new(|| {
+                let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new_capturing(|k: &u8| (k % 3 == 0).then_some(k / 3), |d: &mut TestData, k, third| {
+                    d.append_text(&format!("[{}/3={}]", k, third));
+                    Result::Ok(())
+                }, &state));
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new(|_| true, |d: &mut TestData, k: &u8| {
+                    d.append_text(&k.to_string());
+                    Result::Ok(())
+                }, &state));
+                state
+            });
+            let run_result: AutomatonResult<u8, String> = automaton.run(&mut data);
+            assert_eq!(data.data(), "12[3/3=1]45[6/3=2]7");
+            assert!(matches!(run_result, AutomatonResult::EmptyIter(1)));
+        }
+
+        #[test]
+        fn capturing_connections_run_exit_action_before_operation() {
+            let mut data = TestData::new(1, 3);
+            let mut automaton = Automaton::new(|| {
+                let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                state.borrow_mut().set_exit_action(|d: &mut TestData| {
+                    d.append_text(">");
+                    Result::Ok(())
+                });
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new_capturing(|k: &u8| (*k == 2).then_some("two"), |d: &mut TestData, _, name| {
+                    d.append_text(name);
+                    Result::Ok(())
+                }, &state));
+                state
+            });
+            let run_result: AutomatonResult<u8, String> = automaton.run(&mut data);
+            assert_eq!(data.data(), "");
+            assert!(matches!(run_result, AutomatonResult::CouldNotFindNextState(1, _)));
+            let mut data = TestData::new(2, 3);
+            automaton.run(&mut data);
+            assert_eq!(data.data(), ">two");
+        }
+
+        #[test]
+        fn entry_and_exit_actions_are_executed_in_order() {
+            let mut data = TestData::new(1, 3);
+            let mut automaton = Automaton::new(|| {
+                let first = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                let second = new_shared_concrete_state(SimpleStateImplementation::new(2));
+                first.borrow_mut().set_entry_action(|d: &mut TestData| {
+                    d.append_text("<1");
+                    Result::Ok(())
+                });
+                first.borrow_mut().set_exit_action(|d: &mut TestData| {
+                    d.append_text("1>");
+                    Result::Ok(())
+                });
+                second.borrow_mut().set_entry_action(|d: &mut TestData| {
+                    d.append_text("<2");
+                    Result::Ok(())
+                });
+                first.borrow_mut().register_connection(SimpleInterStateConnection::new(|k| *k == 1, |d: &mut TestData, _| {
+                    d.append_text("-");
+                    Result::Ok(())
+                }, &first));
+                first.borrow_mut().register_connection(SimpleInterStateConnection::new(|k| *k == 2, |d: &mut TestData, _| {
+                    d.append_text("=");
+                    Result::Ok(())
+                }, &second));
+                first
+            });
+            let run_result: AutomatonResult<u8, String> = automaton.run(&mut data);
+            assert_eq!(data.data(), "<11>-<11>=<2");
+            assert!(matches!(run_result, AutomatonResult::EmptyIter(2)));
+        }
+
+        #[test]
+        fn entry_action_error_stops_automaton() {
+            let mut data = TestData::new(1, 3);
+            let mut automaton = Automaton::new(|| {
+                let first = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                let second = new_shared_concrete_state(SimpleStateImplementation::new(2));
+                second.borrow_mut().set_entry_action(|_: &mut TestData| Result::Err(String::from("Entry failed")));
+                first.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|_| true, &second));
+                second.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|_| true, &first));
+                first
+            });
+            let run_result: AutomatonResult<u8, String> = automaton.run(&mut data);
+            assert!(matches!(run_result, AutomatonResult::Error(ref err, _) if err == "Entry failed"));
+        }
+
+        #[test]
+        fn fallback_connection_is_used_when_nothing_matches() {
+            let mut data = TestData::new(1, 6);
+            let mut automaton = Automaton::new(|| {
+                let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new(|k| k % 2 == 0, |d: &mut TestData, _| {
+                    d.append_text("even ");
+                    Result::Ok(())
+                }, &state));
+                state.borrow_mut().set_fallback_connection(|d: &mut TestData, k: &u8| {
+                    d.append_text(&format!("{} ", k));
+                    Result::Ok(())
+                }, &state);
+                state
+            });
+            let run_result: AutomatonResult<u8, String> = automaton.run(&mut data);
+            assert_eq!(data.data(), "1 even 3 even 5 ");
+            assert!(matches!(run_result, AutomatonResult::EmptyIter(1)));
+        }
+
+        #[test]
+        fn epsilon_connections_do_not_consume_keys() {
+            let mut data = TestData::new(1, 4);
+            let mut automaton = Automaton::new(|| {
+                let setup = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                let reading = new_shared_concrete_state(SimpleStateImplementation::new(2));
+                let finished = new_shared_concrete_state(SimpleStateImplementation::new(3));
+                setup.borrow_mut().register_epsilon_connection(EpsilonConnection::new(|d: &mut TestData| {
+                    d.append_text("setup ");
+                    Result::Ok(())
+                }, &reading));
+                reading.borrow_mut().register_epsilon_connection(EpsilonConnection::new_guarded(|d: &TestData| d.data().ends_with("2 "), |d: &mut TestData| {
+                    d.append_text("finish ");
+                    Result::Ok(())
+                }, &finished));
+                for state in [&reading, &finished] {
+                    state.borrow_mut().set_fallback_connection(|d: &mut TestData, k: &u8| {
+                        d.append_text(&format!("{} ", k));
+                        Result::Ok(())
+                    }, state);
+                }
+                setup
+            });
+            let run_result: AutomatonResult<u8, String> = automaton.run(&mut data);
+            assert_eq!(data.data(), "setup 1 2 finish 3 ");
+            assert!(matches!(run_result, AutomatonResult::EmptyIter(3)));
+        }
+
+        #[test]
+        fn epsilon_cycle_stops_automaton() {
+            let mut data = TestData::new(1, 3);
+            let mut automaton = Automaton::new(|| {
+                let first = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                let second = new_shared_concrete_state(SimpleStateImplementation::new(2));
+                first.borrow_mut().register_epsilon_connection(EpsilonConnection::new_no_action(&second));
+                second.borrow_mut().register_epsilon_connection(EpsilonConnection::new_no_action(&first));
+                first
+            });
+            let run_result: AutomatonResult<u8, String> = automaton.run(&mut data);
+            assert!(matches!(run_result, AutomatonResult::EpsilonCycle(2)));
+            assert_eq!(data.current, 1);
+        }
+
+        #[test]
+        fn epsilon_cycle_is_detected_across_paused_runs() {
+            let mut data = TestData::new(1, 3);
+            let mut automaton = Automaton::new(|| {
+                let first = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                let second = new_shared_concrete_state(SimpleStateImplementation::new(2));
+                first.borrow_mut().register_epsilon_connection(EpsilonConnection::new_no_action(&second));
+                second.borrow_mut().register_epsilon_connection(EpsilonConnection::new_no_action(&first));
+                first
+            });
+            assert!(automaton.run_steps(&mut data, 1).is_none());
+            let run_result: Option<AutomatonResult<u8, String>> = automaton.run_steps(&mut data, 1);
+            assert!(matches!(run_result, Option::Some(AutomatonResult::EpsilonCycle(2))));
+        }
+
+        #[test]
+        fn guarded_epsilon_loop_ends_with_its_guard() {
+            let mut data = TestData::new(1, 2);
+            let mut automaton = Automaton::new(|| {
+                let counting = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                let step = new_shared_concrete_state(SimpleStateImplementation::new(2));
+                counting.borrow_mut().register_epsilon_connection(EpsilonConnection::new_guarded(|d: &TestData| d.data().len() < 3, |d: &mut TestData| {
+                    d.append_text("+");
+                    Result::Ok(())
+                }, &step));
+                step.borrow_mut().register_epsilon_connection(EpsilonConnection::new_no_action(&counting));
+                counting.borrow_mut().set_fallback_connection_no_action(&counting);
+                counting
+            });
+            let run_result: AutomatonResult<u8, String> = automaton.run(&mut data);
+            assert_eq!(data.data(), "+++");
+            assert!(matches!(run_result, AutomatonResult::EmptyIter(1)));
+        }
+
+        #[test]
+        fn unconditional_epsilon_after_failed_guard_is_detected_as_cycle() {
+            let mut data = TestData::new(1, 3);
+            let mut automaton = Automaton::new(|| {
+                let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                state.borrow_mut().register_epsilon_connection(EpsilonConnection::new_guarded_no_action(|_: &TestData| false, &state));
+                state.borrow_mut().register_epsilon_connection(EpsilonConnection::new_no_action(&state));
+                state
+            });
+            let run_result: Option<AutomatonResult<u8, String>> = automaton.run_steps(&mut data, 1_000_000);
+            assert!(matches!(run_result, Option::Some(AutomatonResult::EpsilonCycle(1))));
+        }
+
+        #[test]
+        fn guarded_epsilon_loop_stops_at_limit() {
+            let mut data = TestData::new(1, 3);
+            let mut automaton = Automaton::new(|| {
+                let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                state.borrow_mut().register_epsilon_connection(EpsilonConnection::new_guarded(|_: &TestData| true, |d: &mut TestData| {
+                    d.append_text("+");
+                    Result::Ok(())
+                }, &state));
+                state
+            }).with_epsilon_limit(5);
+            let run_result: AutomatonResult<u8, String> = automaton.run(&mut data);
+            assert!(matches!(run_result, AutomatonResult::EpsilonCycle(1)));
+            assert_eq!(data.data(), "++++++");
+        }
+
+        struct Words {
+            keys: std::collections::VecDeque<char>,
+            words: Vec<String>,
+        }
+
+        impl KeyProvidingData<char> for Words {
+            fn next_key(&mut self) -> Option<char> {
+                self.keys.next_key()
+            }
+
+            fn restore_unmatched_key(&mut self, key: char) -> bool {
+                self.keys.restore_unmatched_key(key)
+            }
+        }
+
+        impl PushbackKeyProvidingData<char> for Words {
+            fn push_back_key(&mut self, key: char) {
+                self.keys.push_back_key(key);
+            }
+        }
+
+        #[test]
+        fn sequence_connections_backtrack_on_partial_match() {
+            let mut data = Words { keys: "whilwhile42x".chars().collect(), words: Vec::new() };
+            let mut automaton: Automaton<u8, Words, String> = Automaton::new(|| {
+                let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new_sequence("while".chars().collect(), |d: &mut Words, _| {
+                    d.words.push(String::from("WHILE"));
+                    Result::Ok(())
+                }, &state));
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new_slice(2, |keys: &[char]| keys.iter().all(char::is_ascii_digit), |d: &mut Words, _| {
+                    d.words.push(String::from("NUMBER"));
+                    Result::Ok(())
+                }, &state));
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new(|k: &char| k.is_alphabetic(), |d: &mut Words, k| {
+                    d.words.push(k.to_string());
+                    Result::Ok(())
+                }, &state));
+                state
+            });
+            let run_result = automaton.run(&mut data);
+            assert!(matches!(run_result, AutomatonResult::EmptyIter(1)));
+            assert_eq!(data.words, vec!["w", "h", "i", "l", "WHILE", "NUMBER", "x"]);
+        }
+
+        #[test]
+        fn empty_sequences_and_slices_never_match() {
+            let mut data = Words { keys: "ab".chars().collect(), words: Vec::new() };
+            let mut automaton: Automaton<u8, Words, String> = Automaton::new(|| {
+                let state = new_shared_concrete_state(SimpleStateImplementation::new(1));
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new_sequence(Vec::new(), |d: &mut Words, _| {
+                    d.words.push(String::from("SEQUENCE"));
+                    Result::Ok(())
+                }, &state));
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new_slice(0, |_: &[char]| true, |d: &mut Words, _| {
+                    d.words.push(String::from("SLICE"));
+                    Result::Ok(())
+                }, &state));
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new(|k: &char| *k == 'a', |d: &mut Words, k| {
+                    d.words.push(k.to_string());
+                    Result::Ok(())
+                }, &state));
+                state
+            });
+            let run_result = automaton.run(&mut data);
+            assert!(matches!(run_result, AutomatonResult::CouldNotFindNextState(1, _)));
+            assert_eq!(data.words, vec!["a"]);
+            assert_eq!(data.keys, vec!['b']);
         }
     }
 }
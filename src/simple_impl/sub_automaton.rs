@@ -0,0 +1,240 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{automaton::{Automaton, AutomatonResult, NextState, RunProgress}, automaton_state::{convert_to_dyn_reference, AutomatonState, SharedAutomatonState}};
+
+/// Classification of the result of an inner automaton run. Used for choosing the state an outer automaton will move to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubAutomatonOutcome {
+    /// Inner automaton stopped (for any reason other than an error) on one of the accepting states.
+    Accepted,
+    /// Inner automaton couldn't find a connection for a key on a non accepting state.
+    NotFound,
+    /// Inner automaton got stuck in a cycle of epsilon transitions.
+    EpsilonCycle,
+    /// Inner automaton ran out of keys on a non accepting state.
+    EmptyInput,
+    /// Operation executed by the inner automaton returned an error.
+    Error,
+}
+
+/// State that runs another automaton on the shared data whenever it is executed. The outer automaton then moves to the
+/// state connected with the outcome of the inner run. Allows for reusing finished automata as parts of bigger graphs.
+///
+/// When there is no state connected with an outcome:
+/// * `Accepted` and `EmptyInput` end the process (outer automaton returns `EmptyIter` with this state's identifier),
+/// * `NotFound` makes outer automaton return `CouldNotFindNextState` with this state's identifier,
+/// * `EpsilonCycle` makes outer automaton return `EpsilonCycle` with this state's identifier,
+/// * `Error` is passed to the outer automaton.
+///
+/// Connecting a state with the `Error` outcome drops the error returned by the inner automaton.
+///
+/// Inner run usually ends on a key that none of its connections matches (e.g. a separator after a number). Such key is
+/// passed to [`KeyProvidingData::restore_unmatched_key`](crate::key_provider::KeyProvidingData::restore_unmatched_key),
+/// so if the data can take keys back, the state connected with the outcome receives it as its next key. Otherwise the
+/// key is lost.
+///
+/// Inner run which didn't take any transition consuming a key leaves the input unchanged, so moving to the connected
+/// state is treated by the outer automaton as an epsilon transition (unconditional unless the inner run took guarded
+/// epsilon transitions). Loops through such runs are stopped with `EpsilonCycle` instead of repeating forever. This also
+/// applies when the stop key was lost because the data can't take it back.
+pub struct SubAutomatonState<'a, Id, InnerId, D, E> {
+    id: Id,
+    inner: RefCell<Automaton<'a, InnerId, D, E>>,
+    accepting_states: Vec<InnerId>,
+    outcome_connections: Vec<(SubAutomatonOutcome, SharedAutomatonState<'a, Id, D, E>)>,
+}
+
+impl <'a, Id, InnerId, D, E> SubAutomatonState<'a, Id, InnerId, D, E> where Id: Copy, InnerId: PartialEq {
+    /// Creates new state wrapping provided automaton.
+    ///
+    /// * `id` - Identifier of this state.
+    /// * `inner` - Automaton that will be run on the shared data.
+    /// * `accepting_states` - Identifiers of inner states which are treated as successful end of the inner run.
+    pub fn new(id: Id, inner: Automaton<'a, InnerId, D, E>, accepting_states: Vec<InnerId>) -> Self {
+        Self { id, inner: RefCell::new(inner), accepting_states, outcome_connections: Vec::new() }
+    }
+
+    /// Sets state that will become active after inner run ends with given outcome. Replaces previously connected state.
+    pub fn register_outcome_connection<S: AutomatonState<'a, Id, D, E> + 'a>(&mut self, outcome: SubAutomatonOutcome, next_state: &Rc<RefCell<S>>) {
+        self.outcome_connections.retain(|(o, _)| *o != outcome);
+        self.outcome_connections.push((outcome, convert_to_dyn_reference(Rc::clone(next_state))));
+    }
+
+    fn connected_state(&self, outcome: SubAutomatonOutcome) -> Option<SharedAutomatonState<'a, Id, D, E>> {
+        self.outcome_connections.iter().find(|(o, _)| *o == outcome).map(|(_, state)| Rc::clone(state))
+    }
+
+    fn classify(&self, result: &AutomatonResult<InnerId, E>) -> SubAutomatonOutcome {
+        match result {
+            AutomatonResult::EmptyIter(id) | AutomatonResult::CouldNotFindNextState(id, _) if self.accepting_states.contains(id) => SubAutomatonOutcome::Accepted,
+            AutomatonResult::EmptyIter(_) => SubAutomatonOutcome::EmptyInput,
+            AutomatonResult::CouldNotFindNextState(_, _) => SubAutomatonOutcome::NotFound,
+            AutomatonResult::EpsilonCycle(_) => SubAutomatonOutcome::EpsilonCycle,
+            AutomatonResult::Error(_, _) => SubAutomatonOutcome::Error,
+        }
+    }
+}
+
+impl <'a, Id, InnerId, D, E> AutomatonState<'a, Id, D, E> for SubAutomatonState<'a, Id, InnerId, D, E> where Id: Copy, InnerId: PartialEq {
+    fn get_id_owned(&self) -> Id {
+        self.id
+    }
+
+    fn get_id(&self) -> &Id {
+        &self.id
+    }
+
+    /// Runs inner automaton and returns state connected with the outcome of the run.
+    fn execute_next_connection(&self, data: &mut D) -> Result<NextState<'a, Id, D, E>, E> {
+        let mut inner = self.inner.borrow_mut();
+        let result = inner.run(data);
+        let outcome = self.classify(&result);
+        if let Option::Some(next_state) = self.connected_state(outcome) {
+            return Result::Ok(match inner.progress() {
+                RunProgress::ConsumedInput => NextState::Continue(next_state),
+                RunProgress::GuardedEpsilon => NextState::GuardedEpsilon(next_state),
+                RunProgress::Unchanged => NextState::Epsilon(next_state),
+            });
+        }
+        match result {
+            AutomatonResult::Error(err, _) => Result::Err(err),
+            AutomatonResult::EpsilonCycle(_) => Result::Ok(NextState::EpsilonCycle),
+            _ if outcome == SubAutomatonOutcome::NotFound => Result::Ok(NextState::NotFound),
+            _ => Result::Ok(NextState::ProcessEnded),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use crate::{automaton::{Automaton, AutomatonResult}, automaton_state::new_shared_concrete_state, simple_impl::{key_sources::KeyedData, simple_state::{EpsilonConnection, KeyProvidingData, SimpleInterStateConnection, SimpleStateImplementation}}};
+
+    use super::{SubAutomatonOutcome, SubAutomatonState};
+
+    #[derive(Default)]
+    struct Parsed {
+        numbers: Vec<u32>,
+        separators: usize,
+    }
+
+    type TestData = KeyedData<VecDeque<char>, Parsed>;
+
+    fn test_data(text: &str) -> TestData {
+        KeyedData::new(text.chars().collect(), Parsed::default())
+    }
+
+    /// Recognises numbers and stores their values. Stops on the first character that isn't a digit.
+    fn number_automaton<'a>() -> Automaton<'a, u8, TestData, String> {
+        Automaton::new(|| {
+            let start = new_shared_concrete_state(SimpleStateImplementation::new(0));
+            let digits = new_shared_concrete_state(SimpleStateImplementation::new(1));
+            start.borrow_mut().register_connection(SimpleInterStateConnection::new(|k: &char| k.is_ascii_digit(), |d: &mut TestData, k| {
+                d.data.numbers.push(k.to_digit(10).unwrap());
+                Result::Ok(())
+            }, &digits));
+            digits.borrow_mut().register_connection(SimpleInterStateConnection::new(|k: &char| k.is_ascii_digit(), |d: &mut TestData, k| {
+                let number = d.data.numbers.last_mut().unwrap();
+                *number = *number * 10 + k.to_digit(10).unwrap();
+                Result::Ok(())
+            }, &digits));
+            digits.borrow_mut().register_connection(SimpleInterStateConnection::new(|k| *k == '!', |_: &mut TestData, _| {
+                Result::Err(String::from("Unexpected '!'"))
+            }, &digits));
+            start
+        })
+    }
+
+    /// Reads numbers separated with single characters. Skips everything up to the next ';' if number is not found.
+    fn outer_automaton<'a>() -> Automaton<'a, u32, TestData, String> {
+        Automaton::new(|| {
+            let numbers = new_shared_concrete_state(SubAutomatonState::new(1, number_automaton(), vec![1]));
+            let rejected = new_shared_concrete_state(SimpleStateImplementation::new(2));
+            numbers.borrow_mut().register_outcome_connection(SubAutomatonOutcome::Accepted, &numbers);
+            numbers.borrow_mut().register_outcome_connection(SubAutomatonOutcome::NotFound, &rejected);
+            rejected.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k: &char| *k == ';', &numbers));
+            rejected.borrow_mut().set_fallback_connection_no_action(&rejected);
+            numbers
+        })
+    }
+
+    #[test]
+    fn sub_automaton_moves_to_state_connected_with_outcome() {
+        let mut data = test_data("12;3;x4;56;");
+        let result = outer_automaton().run(&mut data);
+        assert!(matches!(result, AutomatonResult::EmptyIter(1)));
+        assert_eq!(data.data.numbers, vec![12, 3, 56]);
+    }
+
+    #[test]
+    fn sub_automaton_passes_not_connected_error() {
+        let mut data = test_data("12;3!");
+        let result = outer_automaton().run(&mut data);
+        assert!(matches!(result, AutomatonResult::Error(ref err, _) if err == "Unexpected '!'"));
+        assert_eq!(data.data.numbers, vec![12, 3]);
+    }
+
+    fn looping_automaton<'a>() -> Automaton<'a, u8, TestData, String> {
+        Automaton::new(|| {
+            let state = new_shared_concrete_state(SimpleStateImplementation::new(0));
+            state.borrow_mut().register_epsilon_connection(EpsilonConnection::new_no_action(&state));
+            state
+        })
+    }
+
+    #[test]
+    fn sub_automaton_reports_epsilon_cycle_separately() {
+        let mut automaton: Automaton<u32, TestData, String> = Automaton::new(|| {
+            let looping = new_shared_concrete_state(SubAutomatonState::new(1, looping_automaton(), vec![]));
+            let rejected = new_shared_concrete_state(SimpleStateImplementation::new(2));
+            looping.borrow_mut().register_outcome_connection(SubAutomatonOutcome::NotFound, &rejected);
+            looping
+        });
+        assert!(matches!(automaton.run(&mut test_data("1")), AutomatonResult::EpsilonCycle(1)));
+
+        let mut automaton: Automaton<u32, TestData, String> = Automaton::new(|| {
+            let looping = new_shared_concrete_state(SubAutomatonState::new(1, looping_automaton(), vec![]));
+            let stuck = new_shared_concrete_state(SimpleStateImplementation::new(3));
+            looping.borrow_mut().register_outcome_connection(SubAutomatonOutcome::EpsilonCycle, &stuck);
+            looping
+        });
+        assert!(matches!(automaton.run(&mut test_data("1")), AutomatonResult::CouldNotFindNextState(3, _)));
+    }
+
+    #[test]
+    fn stop_key_of_inner_run_is_available_for_outer_state() {
+        let mut automaton: Automaton<u32, TestData, String> = Automaton::new(|| {
+            let numbers = new_shared_concrete_state(SubAutomatonState::new(1, number_automaton(), vec![1]));
+            let separator = new_shared_concrete_state(SimpleStateImplementation::new(2));
+            numbers.borrow_mut().register_outcome_connection(SubAutomatonOutcome::Accepted, &separator);
+            separator.borrow_mut().register_connection(SimpleInterStateConnection::new(|k| *k == ';', |d: &mut TestData, _| {
+                d.data.separators += 1;
+                Result::Ok(())
+            }, &numbers));
+            numbers
+        });
+        let mut data = test_data("12;3;4x");
+        let result = automaton.run(&mut data);
+        assert!(matches!(result, AutomatonResult::CouldNotFindNextState(2, _)));
+        assert_eq!(data.data.numbers, vec![12, 3, 4]);
+        assert_eq!(data.data.separators, 2);
+        assert_eq!(data.next_key(), Option::Some('x'));
+    }
+
+    #[test]
+    fn inner_run_without_consumed_keys_does_not_loop_forever() {
+        let looping_outer = || -> Automaton<u32, TestData, String> {
+            Automaton::new(|| {
+                let numbers = new_shared_concrete_state(SubAutomatonState::new(1, number_automaton(), vec![0, 1]));
+                numbers.borrow_mut().register_outcome_connection(SubAutomatonOutcome::Accepted, &numbers);
+                numbers
+            })
+        };
+        let mut data = test_data("1x");
+        assert!(matches!(looping_outer().run(&mut data), AutomatonResult::EpsilonCycle(1)));
+        assert_eq!(data.data.numbers, vec![1]);
+        assert_eq!(data.next_key(), Option::Some('x'));
+        assert!(matches!(looping_outer().run(&mut test_data("")), AutomatonResult::EpsilonCycle(1)));
+    }
+}
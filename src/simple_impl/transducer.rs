@@ -0,0 +1,237 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use crate::{automaton::{Automaton, AutomatonResult}, automaton_state::AutomatonState, key_provider::{KeyProvidingData, PeekingKeyProvidingData, PushbackKeyProvidingData}, simple_impl::{key_sources::PeekableKeys, simple_state::{SimpleInterStateConnection, SimpleStateImplementation}}};
+
+/// Receiver of symbols emitted by transducing connections.
+pub trait OutputSink<O> {
+    fn emit(&mut self, symbol: O);
+}
+
+impl <O> OutputSink<O> for Vec<O> {
+    fn emit(&mut self, symbol: O) {
+        self.push(symbol);
+    }
+}
+
+/// Emitted symbols are queued at the back, so the queue can be used as keys for the next transducer.
+impl <O> OutputSink<O> for VecDeque<O> {
+    fn emit(&mut self, symbol: O) {
+        self.push_back(symbol);
+    }
+}
+
+impl OutputSink<char> for String {
+    fn emit(&mut self, symbol: char) {
+        self.push(symbol);
+    }
+}
+
+impl OutputSink<&str> for String {
+    fn emit(&mut self, symbol: &str) {
+        self.push_str(symbol);
+    }
+}
+
+/// Data of a transducer (Mealy machine) made of the data providing keys and the sink receiving output symbols. Keys
+/// are taken (and peeked or pushed back, if the data allows it) from the `input` field.
+pub struct Transduction<T, Out> {
+    pub input: T,
+    pub output: Out,
+}
+
+impl <T, Out> Transduction<T, Out> {
+    /// Combines data providing keys with the output sink.
+    pub fn new(input: T, output: Out) -> Self {
+        Self { input, output }
+    }
+}
+
+impl <K, T: KeyProvidingData<K>, Out> KeyProvidingData<K> for Transduction<T, Out> {
+    fn next_key(&mut self) -> Option<K> {
+        self.input.next_key()
+    }
+
+    fn restore_unmatched_key(&mut self, key: K) -> bool {
+        self.input.restore_unmatched_key(key)
+    }
+}
+
+impl <K, T: PeekingKeyProvidingData<K>, Out> PeekingKeyProvidingData<K> for Transduction<T, Out> {
+    fn peek_key(&mut self, offset: usize) -> Option<K> {
+        self.input.peek_key(offset)
+    }
+}
+
+impl <K, T: PushbackKeyProvidingData<K>, Out> PushbackKeyProvidingData<K> for Transduction<T, Out> {
+    fn push_back_key(&mut self, key: K) {
+        self.input.push_back_key(key);
+    }
+}
+
+/// Simple state of a transducer. Connections created with [`SimpleInterStateConnection::new_transducing`] and
+/// [`SimpleInterStateConnection::new_emitting`] emit output symbols while changing state.
+pub type TransducerState<'a, K, Id, T, Out, E> = SimpleStateImplementation<'a, K, Id, Transduction<T, Out>, E>;
+
+impl <'a, K, Id, T, Out, E> SimpleInterStateConnection<'a, K, Id, Transduction<T, Out>, E> where Id: Copy {
+    /// Creates new connection with specified matcher and a procedure that will be executed when this connection is
+    /// matched. Procedure receives the data (without the output) and can emit any number of symbols into the sink.
+    pub fn new_transducing<M: Fn(&K) -> bool + 'a, FEmit: Fn(&mut T, &K, &mut Out) -> Result<(), E> + 'a, S: AutomatonState<'a, Id, Transduction<T, Out>, E> + 'a>(matcher: M, emit_function: FEmit, next_state: &Rc<RefCell<S>>) -> Self {
+        Self::new(matcher, move |transduction: &mut Transduction<T, Out>, key: &K| emit_function(&mut transduction.input, key, &mut transduction.output), next_state)
+    }
+
+    /// Creates new connection with specified matcher which emits given symbols when matched.
+    pub fn new_emitting<O: Clone + 'a, M: Fn(&K) -> bool + 'a, S: AutomatonState<'a, Id, Transduction<T, Out>, E> + 'a>(matcher: M, symbols: Vec<O>, next_state: &Rc<RefCell<S>>) -> Self
+    where Out: OutputSink<O> {
+        Self::new_transducing(matcher, move |_: &mut T, _: &K, output: &mut Out| {
+            for symbol in &symbols {
+                output.emit(symbol.clone());
+            }
+            Result::Ok(())
+        }, next_state)
+    }
+}
+
+impl <'a, Id, T, Out, E> Automaton<'a, Id, Transduction<T, Out>, E> where Out: Default {
+    /// Runs automaton on the data collecting emitted symbols into a new sink (e.g. `Vec` or `String`). Returns the
+    /// result of the run and the collected output. For automata made of [`MooreState`](crate::simple_impl::moore::MooreState)
+    /// states the output is the sequence of outputs of visited states.
+    pub fn transduce(&mut self, data: T) -> (AutomatonResult<Id, E>, Out) {
+        let mut transduction = Transduction::new(data, Out::default());
+        let result = self.run(&mut transduction);
+        (result, transduction.output)
+    }
+}
+
+impl <'a, Id, T, O, E> Automaton<'a, Id, Transduction<T, VecDeque<O>>, E> {
+    /// Turns automaton into an iterator over symbols emitted while transducing keys provided by the data. Automaton is
+    /// executed only until it emits the next symbol, so output can be consumed (e.g. by the next step of a pipeline) while
+    /// the input is still being read.
+    pub fn transduce_iter(self, data: T) -> Transducing<'a, Id, T, O, E> {
+        Transducing { automaton: self, transduction: Transduction::new(data, VecDeque::new()), result: Option::None }
+    }
+}
+
+/// Iterator over symbols emitted by a transducer, created with `Automaton::transduce_iter`. Keys can't carry errors, so
+/// the iteration ends when the automaton stops and the result of the run is stored for later inspection (like errors of
+/// [`ReadBytes`](crate::simple_impl::key_sources::ReadBytes)).
+///
+/// Steps are chained into pipelines with [`Transducing::then`] - each step takes keys from the output of the previous
+/// one, so symbols flow through the whole pipeline one by one.
+pub struct Transducing<'a, Id, T, O, E> {
+    automaton: Automaton<'a, Id, Transduction<T, VecDeque<O>>, E>,
+    transduction: Transduction<T, VecDeque<O>>,
+    result: Option<AutomatonResult<Id, E>>,
+}
+
+impl <'a, Id, T, O, E> Transducing<'a, Id, T, O, E> {
+    /// Returns result of the run. Returns `None` if the automaton hasn't stopped yet.
+    pub fn result(&self) -> Option<&AutomatonResult<Id, E>> {
+        self.result.as_ref()
+    }
+
+    /// Returns whether the automaton has stopped because its input has ended (the whole input was transduced).
+    pub fn is_complete(&self) -> bool {
+        self.result.as_ref().is_some_and(AutomatonResult::is_empty_iter)
+    }
+
+    /// Returns data providing keys (for a pipeline step - the previous step wrapped in [`PeekableKeys`]).
+    pub fn get_ref(&self) -> &T {
+        &self.transduction.input
+    }
+
+    /// Creates pipeline step passing symbols emitted by this transducer as keys to the next automaton.
+    pub fn then<Id2, O2, E2>(self, next: Automaton<'a, Id2, Transduction<PeekableKeys<Self>, VecDeque<O2>>, E2>) -> Transducing<'a, Id2, PeekableKeys<Self>, O2, E2> {
+        next.transduce_iter(PeekableKeys::new(self))
+    }
+}
+
+impl <'a, Id, T, O, E> Iterator for Transducing<'a, Id, T, O, E> {
+    type Item = O;
+
+    fn next(&mut self) -> Option<O> {
+        loop {
+            if let Option::Some(symbol) = self.transduction.output.pop_front() {
+                return Option::Some(symbol);
+            }
+            if self.result.is_some() {
+                return Option::None;
+            }
+            self.result = self.automaton.run_steps(&mut self.transduction, 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use crate::{automaton::{Automaton, AutomatonResult}, automaton_state::new_shared_concrete_state, key_provider::KeyProvidingData, simple_impl::{key_sources::{chars, keys, PeekableKeys}, simple_state::SimpleInterStateConnection}};
+
+    use super::{OutputSink, TransducerState, Transduction};
+
+    /// Escapes HTML special characters, copies other characters.
+    fn escaping<'a, S: KeyProvidingData<char> + 'a>() -> Automaton<'a, u8, Transduction<S, VecDeque<char>>, String> {
+        Automaton::new(|| {
+            let state = new_shared_concrete_state(TransducerState::new(1));
+            state.borrow_mut().register_connection(SimpleInterStateConnection::new_emitting(|k| *k == '<', "&lt;".chars().collect(), &state));
+            state.borrow_mut().register_connection(SimpleInterStateConnection::new_emitting(|k| *k == '>', "&gt;".chars().collect(), &state));
+            state.borrow_mut().register_connection(SimpleInterStateConnection::new_transducing(|k: &char| !k.is_control(), |_, k, output: &mut VecDeque<char>| {
+                output.emit(*k);
+                Result::Ok(())
+            }, &state));
+            state
+        })
+    }
+
+    /// Collapses sequences of whitespace into single spaces.
+    fn normalising<'a, S: KeyProvidingData<char> + 'a>() -> Automaton<'a, u8, Transduction<S, VecDeque<char>>, String> {
+        Automaton::new(|| {
+            let text = new_shared_concrete_state(TransducerState::new(1));
+            let space = new_shared_concrete_state(TransducerState::new(2));
+            text.borrow_mut().register_connection(SimpleInterStateConnection::new_emitting(|k: &char| k.is_whitespace(), vec![' '], &space));
+            space.borrow_mut().register_connection(SimpleInterStateConnection::new_no_action(|k: &char| k.is_whitespace(), &space));
+            for state in [&text, &space] {
+                state.borrow_mut().register_connection(SimpleInterStateConnection::new_transducing(|_| true, |_, k, output: &mut VecDeque<char>| {
+                    output.emit(*k);
+                    Result::Ok(())
+                }, &text));
+            }
+            text
+        })
+    }
+
+    #[test]
+    fn transducer_collects_output_into_string() {
+        let mut automaton: Automaton<u8, Transduction<PeekableKeys<std::str::Chars>, String>, String> = Automaton::new(|| {
+            let state = new_shared_concrete_state(TransducerState::new(1));
+            state.borrow_mut().register_connection(SimpleInterStateConnection::new_emitting(|k| *k == '&', vec!["&amp;"], &state));
+            state.borrow_mut().register_connection(SimpleInterStateConnection::new_transducing(|_| true, |_, k: &char, output: &mut String| {
+                output.emit(k.to_ascii_uppercase());
+                Result::Ok(())
+            }, &state));
+            state
+        });
+        let (result, output) = automaton.transduce(chars("a&b"));
+        assert!(matches!(result, AutomatonResult::EmptyIter(1)));
+        assert_eq!(output, "A&amp;B");
+    }
+
+    #[test]
+    fn transducers_are_chained_in_pipeline() {
+        let mut pipeline = escaping().transduce_iter(chars("a  <b>   c")).then(normalising());
+        assert_eq!(pipeline.by_ref().collect::<String>(), "a &lt;b&gt; c");
+        assert!(pipeline.is_complete() && pipeline.get_ref().get_ref().is_complete());
+
+        let mut pipeline = escaping().transduce_iter(chars("a\u{7}b")).then(normalising());
+        assert_eq!(pipeline.by_ref().collect::<String>(), "a");
+        assert!(pipeline.is_complete());
+        assert!(matches!(pipeline.get_ref().get_ref().result(), Option::Some(AutomatonResult::CouldNotFindNextState(1, _))));
+    }
+
+    #[test]
+    fn pipeline_streams_symbols_between_steps() {
+        let mut pipeline = escaping().transduce_iter(keys("a  <".chars().cycle())).then(normalising());
+        assert_eq!(pipeline.by_ref().take(12).collect::<String>(), "a &lt;a &lt;");
+        assert!(pipeline.result().is_none());
+    }
+}